@@ -36,6 +36,13 @@ pub enum BuilderError {
     NoIncludeCxxMacrosFound,
     /// Unable to create one of the directories to which we need to write
     UnableToCreateDirectory(std::io::Error, PathBuf),
+    /// The C++ generated for an `include_cpp` block with its own compiler
+    /// arguments couldn't be compiled.
+    BlockCompileFail(cc::Error),
+    /// Some `include_cpp` blocks have their own compiler arguments, so
+    /// need to be built separately, but the caller can only be given a
+    /// single build. The names are the libraries they would be built into.
+    BlockBuildsNotHandled(Vec<String>),
 }
 
 impl Display for BuilderError {
@@ -46,6 +53,8 @@ impl Display for BuilderError {
             BuilderError::FileWriteFail(ee, pb) => write!(f, "Unable to write to {}: {}", pb.to_string_lossy(), ee)?,
             BuilderError::NoIncludeCxxMacrosFound => write!(f, "No include_cpp! macro found")?,
             BuilderError::UnableToCreateDirectory(ee, pb) => write!(f, "Unable to create directory {}: {}", pb.to_string_lossy(), ee)?,
+            BuilderError::BlockCompileFail(ee) => write!(f, "Unable to compile the C++ generated for an include_cpp! block: {}", ee)?,
+            BuilderError::BlockBuildsNotHandled(names) => write!(f, "Some include_cpp! blocks have their own compiler arguments and must be built separately ({}); use build_with_block_builds", names.join(", "))?,
         }
         Ok(())
     }
@@ -53,7 +62,55 @@ impl Display for BuilderError {
 
 pub type BuilderBuild = cc::Build;
 
-pub struct BuilderSuccess(pub BuilderBuild, pub Vec<PathBuf>);
+/// The first field is the build for the generated C++ of every
+/// `include_cpp` block without its own compiler arguments, to which you
+/// can add further files. The second is the list of generated .rs files.
+/// Blocks which do have their own arguments (from `cpp_std!`, `define!`
+/// or `clang_arg!`) each get a separate build: see
+/// [`BuilderSuccess::take_block_builds`].
+pub struct BuilderSuccess(pub BuilderBuild, pub Vec<PathBuf>, Vec<BlockBuild>);
+
+impl BuilderSuccess {
+    /// Take the separate builds for any `include_cpp` blocks with their
+    /// own compiler arguments. Each should be configured in the same way
+    /// as the main build, then compiled using [`BlockBuild::compile`].
+    pub fn take_block_builds(&mut self) -> Vec<BlockBuild> {
+        std::mem::take(&mut self.2)
+    }
+}
+
+/// The build for the generated C++ of a single `include_cpp` block with
+/// its own compiler arguments, kept apart so that those arguments don't
+/// affect any other code.
+pub struct BlockBuild {
+    lib_name: String,
+    build: BuilderBuild,
+    clang_args: Vec<String>,
+}
+
+impl BlockBuild {
+    /// The name of the library into which this block is compiled.
+    pub fn lib_name(&self) -> &str {
+        &self.lib_name
+    }
+
+    /// The build, to which you should add the same flags, include
+    /// directories and other settings as to the main build.
+    pub fn build_mut(&mut self) -> &mut BuilderBuild {
+        &mut self.build
+    }
+
+    /// Compile this block. Its own arguments are added after any you have
+    /// configured, so that for example its `cpp_std!` takes precedence.
+    pub fn compile(mut self) -> Result<(), BuilderError> {
+        for arg in &self.clang_args {
+            self.build.flag(arg);
+        }
+        self.build
+            .try_compile(&self.lib_name)
+            .map_err(BuilderError::BlockCompileFail)
+    }
+}
 
 /// Results of a build.
 pub type BuilderResult = Result<BuilderSuccess, BuilderError>;
@@ -153,8 +210,17 @@ pub(crate) fn build_with_existing_parsed_file(
     let mut counter = 0;
     let mut builder = cc::Build::new();
     builder.cpp(true);
+    let mut block_builders = Vec::new();
     let mut generated_rs = Vec::new();
     for include_cpp in parsed_file.get_autocxxes() {
+        let has_own_args = !include_cpp.clang_args().is_empty();
+        let mut block_builder = cc::Build::new();
+        let builder = if has_own_args {
+            block_builder.cpp(true);
+            &mut block_builder
+        } else {
+            &mut builder
+        };
         for inc_dir in include_cpp.include_dirs() {
             builder.include(inc_dir);
         }
@@ -172,11 +238,22 @@ pub(crate) fn build_with_existing_parsed_file(
             let rs = include_cpp.generate_rs();
             generated_rs.push(write_rs_to_file(&rsdir, &fname, rs)?);
         }
+        if has_own_args {
+            let lib_name = format!(
+                "autocxx-{}",
+                include_cpp.get_rs_filename().trim_end_matches(".rs")
+            );
+            block_builders.push(BlockBuild {
+                lib_name,
+                build: block_builder,
+                clang_args: include_cpp.clang_args().to_vec(),
+            });
+        }
     }
     if counter == 0 {
         Err(BuilderError::NoIncludeCxxMacrosFound)
     } else {
-        Ok(BuilderSuccess(builder, generated_rs))
+        Ok(BuilderSuccess(builder, generated_rs, block_builders))
     }
 }
 
@@ -12,20 +12,86 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::types::Namespace;
-use syn::{parse_quote, Ident, Type};
+use crate::{
+    conversion::api::PairTypes,
+    types::{make_ident, Namespace},
+};
+use syn::{parse_quote, Ident, ReturnType, Type};
 
 #[derive(Clone)]
 pub(crate) enum CppConversionType {
     None,
     FromUniquePtrToValue,
     FromValueToUniquePtr,
+    /// A newly constructed value, returned as a `std::shared_ptr`.
+    FromValueToSharedPtr,
+    /// A `std::optional` of a POD or primitive, passed as a pointer
+    /// which is null if there's no value.
+    FromPtrToOptional,
+    /// A `std::optional` of a non-POD type, passed as a `std::unique_ptr`
+    /// which is null if there's no value.
+    FromUniquePtrToOptional,
+    /// A returned `std::optional` of a POD or primitive. The wrapper
+    /// returns whether there's a value, and writes the value into an
+    /// extra out parameter.
+    FromOptionalToOutPtr,
+    /// A returned `std::optional` of a non-POD type, returned as a
+    /// `std::unique_ptr` which is null if there's no value.
+    FromOptionalToUniquePtr,
+    /// A `std::string_view`, passed as a `rust::Str`.
+    FromStrToStringView,
+    /// A returned `std::string_view`, returned as a `rust::Slice` of bytes
+    /// because `rust::Str` would throw if they're not UTF-8.
+    FromStringViewToBytes,
+    /// A returned `std::pair`, unpacked into a struct shared with cxx
+    /// which has fields of these types.
+    FromPairToStruct(Box<Type>, Box<Type>),
+    /// A C array parameter, passed as a reference to a `std::array`.
+    FromStdArrayToPtr,
+    /// A pointer and length parameter pair, passed as a single `rust::Slice`.
+    FromSliceToPtrAndLen,
+    /// A `std::span`, passed as a `rust::Slice`.
+    FromSliceToSpan,
+    /// A `std::function`, passed as a `rust::Box` of a Rust type which
+    /// wraps a closure.
+    FromBoxToStdFunction(Ident),
+    /// A C function pointer and its accompanying `void*` user data,
+    /// passed as a reference to a Rust type which wraps a closure. The
+    /// closure is owned by a `CallbackHandle` returned to the caller.
+    FromRefToFnPtrWithUserData(Ident, UserData),
+    /// A C function pointer without user data, passed as a `usize`
+    /// because cxx has no way to represent C function pointers.
+    FromUsizeToFnPtr,
+}
+
+/// Where a C callback expects to receive its `void*` user data.
+#[derive(Clone)]
+pub(crate) enum UserDataPosition {
+    First,
+    Last,
+}
+
+/// How a C callback expects to receive its user data.
+#[derive(Clone)]
+pub(crate) struct UserData {
+    pub(crate) position: UserDataPosition,
+    /// Whether it's a `const void*` rather than a `void*`.
+    pub(crate) is_const: bool,
 }
 
 #[derive(Clone)]
 pub(crate) enum RustConversionType {
     None,
     FromStr,
+    FromOptionToPtr,
+    FromOptionToUniquePtr,
+    ToOptionFromOutPtr,
+    ToOptionFromUniquePtr,
+    ToStrFromBytes,
+    ToTupleFromStruct,
+    FromClosure,
+    FromClosureKeptInHandle,
+    FromExternFn,
 }
 
 /// A policy for converting types. Conversion may occur on both the Rust and
@@ -63,6 +129,14 @@ impl TypeConversionPolicy {
         }
     }
 
+    pub(crate) fn new_to_shared_ptr(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromValueToSharedPtr,
+            rust_conversion: RustConversionType::None,
+        }
+    }
+
     pub(crate) fn new_from_unique_ptr(ty: Type) -> Self {
         TypeConversionPolicy {
             unwrapped_type: ty,
@@ -79,24 +153,269 @@ impl TypeConversionPolicy {
         }
     }
 
+    /// Array parameters, represented as references to arrays.
+    pub(crate) fn new_from_std_array(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromStdArrayToPtr,
+            rust_conversion: RustConversionType::None,
+        }
+    }
+
+    /// Pointer and length parameters which the user has asked to
+    /// combine into a slice.
+    pub(crate) fn new_from_slice(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromSliceToPtrAndLen,
+            rust_conversion: RustConversionType::None,
+        }
+    }
+
+    /// Parameters of type `std::span<T>`.
+    pub(crate) fn new_span_from_slice(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromSliceToSpan,
+            rust_conversion: RustConversionType::None,
+        }
+    }
+
+    /// Parameters of type `std::optional<T>`, where `T` is POD.
+    pub(crate) fn new_optional_from_ptr(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromPtrToOptional,
+            rust_conversion: RustConversionType::FromOptionToPtr,
+        }
+    }
+
+    /// Parameters of type `std::optional<T>`, where `T` is not POD.
+    pub(crate) fn new_optional_from_unique_ptr(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromUniquePtrToOptional,
+            rust_conversion: RustConversionType::FromOptionToUniquePtr,
+        }
+    }
+
+    /// Return values of type `std::optional<T>`, where `T` is POD.
+    pub(crate) fn new_optional_to_out_ptr(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromOptionalToOutPtr,
+            rust_conversion: RustConversionType::ToOptionFromOutPtr,
+        }
+    }
+
+    /// Return values of type `std::optional<T>`, where `T` is not POD.
+    pub(crate) fn new_optional_to_unique_ptr(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromOptionalToUniquePtr,
+            rust_conversion: RustConversionType::ToOptionFromUniquePtr,
+        }
+    }
+
+    /// Parameters of type `std::string_view`.
+    pub(crate) fn new_string_view_from_str() -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: parse_quote! { &str },
+            cpp_conversion: CppConversionType::FromStrToStringView,
+            rust_conversion: RustConversionType::None,
+        }
+    }
+
+    /// Return values of type `std::string_view`.
+    pub(crate) fn new_string_view_to_str() -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: parse_quote! { &str },
+            cpp_conversion: CppConversionType::FromStringViewToBytes,
+            rust_conversion: RustConversionType::ToStrFromBytes,
+        }
+    }
+
+    /// Return values of type `std::pair`, where both elements are POD,
+    /// primitive or a string.
+    pub(crate) fn new_pair_to_struct(ty: Type, pair_types: &PairTypes) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromPairToStruct(
+                Box::new(PairTypes::field_type(&pair_types.first)),
+                Box::new(PairTypes::field_type(&pair_types.second)),
+            ),
+            rust_conversion: RustConversionType::ToTupleFromStruct,
+        }
+    }
+
+    /// Parameters of type `std::function<R(Args...)>`. `ty` is the
+    /// signature of the callback, as a Rust `fn` type.
+    pub(crate) fn new_std_function_from_closure(ty: Type, closure_type: Ident) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromBoxToStdFunction(closure_type),
+            rust_conversion: RustConversionType::FromClosure,
+        }
+    }
+
+    /// C function pointer parameters which take `void*` user data.
+    /// `ty` is the signature of the callback, without the user data.
+    pub(crate) fn new_fn_ptr_from_closure(
+        ty: Type,
+        closure_type: Ident,
+        user_data: UserData,
+    ) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromRefToFnPtrWithUserData(closure_type, user_data),
+            rust_conversion: RustConversionType::FromClosureKeptInHandle,
+        }
+    }
+
+    /// C function pointer parameters without user data, which can only
+    /// be given Rust `extern "C"` functions. `ty` is the signature of
+    /// the callback.
+    pub(crate) fn new_fn_ptr_from_extern_fn(ty: Type) -> Self {
+        TypeConversionPolicy {
+            unwrapped_type: ty,
+            cpp_conversion: CppConversionType::FromUsizeToFnPtr,
+            rust_conversion: RustConversionType::FromExternFn,
+        }
+    }
+
     pub(crate) fn cpp_work_needed(&self) -> bool {
         !matches!(self.cpp_conversion, CppConversionType::None)
     }
 
     pub(crate) fn unconverted_rust_type(&self) -> Type {
         match self.cpp_conversion {
-            CppConversionType::FromValueToUniquePtr => self.make_unique_ptr_type(),
+            CppConversionType::FromValueToUniquePtr
+            | CppConversionType::FromOptionalToUniquePtr => self.make_unique_ptr_type(),
+            CppConversionType::FromValueToSharedPtr => {
+                let innerty = &self.unwrapped_type;
+                parse_quote! {
+                    cxx::SharedPtr < #innerty >
+                }
+            }
+            CppConversionType::FromOptionalToOutPtr => parse_quote! { bool },
+            CppConversionType::FromStringViewToBytes => parse_quote! { &[u8] },
+            CppConversionType::FromPairToStruct(..) => {
+                let struct_name = make_ident(self.pair_struct_name());
+                parse_quote! { #struct_name }
+            }
             _ => self.unwrapped_type.clone(),
         }
     }
 
     pub(crate) fn converted_rust_type(&self) -> Type {
         match self.cpp_conversion {
-            CppConversionType::FromUniquePtrToValue => self.make_unique_ptr_type(),
+            CppConversionType::FromUniquePtrToValue
+            | CppConversionType::FromUniquePtrToOptional => self.make_unique_ptr_type(),
+            CppConversionType::FromPtrToOptional => {
+                let innerty = &self.unwrapped_type;
+                parse_quote! {
+                    *const #innerty
+                }
+            }
+            CppConversionType::FromBoxToStdFunction(ref closure_type) => {
+                parse_quote! {
+                    Box< #closure_type >
+                }
+            }
+            CppConversionType::FromRefToFnPtrWithUserData(ref closure_type, _) => {
+                parse_quote! {
+                    & #closure_type
+                }
+            }
+            CppConversionType::FromUsizeToFnPtr => parse_quote! { usize },
             _ => self.unwrapped_type.clone(),
         }
     }
 
+    /// The Rust type which wraps a closure, if this conversion creates a
+    /// callback from one.
+    pub(crate) fn closure_type(&self) -> Option<&Ident> {
+        match self.cpp_conversion {
+            CppConversionType::FromBoxToStdFunction(ref closure_type)
+            | CppConversionType::FromRefToFnPtrWithUserData(ref closure_type, _) => {
+                Some(closure_type)
+            }
+            _ => None,
+        }
+    }
+
+    /// The argument types and return type of a callback.
+    pub(crate) fn callback_signature(&self) -> (Vec<Type>, Option<Type>) {
+        match &self.unwrapped_type {
+            Type::BareFn(bf) => (
+                bf.inputs.iter().map(|arg| arg.ty.clone()).collect(),
+                match &bf.output {
+                    ReturnType::Default => None,
+                    ReturnType::Type(_, ty) => Some(ty.as_ref().clone()),
+                },
+            ),
+            _ => panic!("Callback conversion applied to a type other than a function"),
+        }
+    }
+
+    /// The types of the fields of the struct into which C++ unpacks a
+    /// pair.
+    pub(crate) fn pair_field_types(&self) -> (&Type, &Type) {
+        match self.cpp_conversion {
+            CppConversionType::FromPairToStruct(ref first, ref second) => (first, second),
+            _ => panic!("Pair conversion applied to a type other than a pair"),
+        }
+    }
+
+    /// The name of the struct, shared with cxx, into which C++ unpacks
+    /// a pair.
+    pub(crate) fn pair_struct_name(&self) -> String {
+        match &self.unwrapped_type {
+            Type::Path(typ) => PairTypes::helper_name(
+                &typ.path.segments.last().unwrap().ident.to_string(),
+                "struct",
+            ),
+            _ => panic!("Pair conversion applied to a type other than a pair"),
+        }
+    }
+
+    /// The extra parameter, if any, into which the C++ wrapper function
+    /// writes its result.
+    pub(crate) fn out_param_rust_type(&self) -> Option<Type> {
+        match self.cpp_conversion {
+            CppConversionType::FromOptionalToOutPtr => {
+                let innerty = &self.unwrapped_type;
+                Some(parse_quote! {
+                    *mut #innerty
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the cxx::bridge function must be `unsafe` because this
+    /// conversion passes raw pointers, irrespective of whether the
+    /// function as seen by the user is `unsafe`.
+    pub(crate) fn bridge_requires_unsafe(&self) -> bool {
+        matches!(
+            self.cpp_conversion,
+            CppConversionType::FromPtrToOptional
+                | CppConversionType::FromOptionalToOutPtr
+                | CppConversionType::FromRefToFnPtrWithUserData(..)
+        )
+    }
+
+    /// Whether the cxx::bridge function refers to this type by reference,
+    /// such that cxx needs to figure out lifetimes.
+    pub(crate) fn bridge_type_is_reference(&self) -> bool {
+        matches!(
+            self.cpp_conversion,
+            CppConversionType::FromStrToStringView
+                | CppConversionType::FromStringViewToBytes
+                | CppConversionType::FromRefToFnPtrWithUserData(..)
+        )
+    }
+
     fn make_unique_ptr_type(&self) -> Type {
         let innerty = &self.unwrapped_type;
         parse_quote! {
@@ -107,6 +426,15 @@ impl TypeConversionPolicy {
     pub(crate) fn rust_work_needed(&self) -> bool {
         !matches!(self.rust_conversion, RustConversionType::None)
     }
+
+    /// Whether the Rust wrapper function returns a `CallbackHandle` which
+    /// owns the closure passed for this parameter.
+    pub(crate) fn needs_callback_handle(&self) -> bool {
+        matches!(
+            self.rust_conversion,
+            RustConversionType::FromClosureKeptInHandle
+        )
+    }
 }
 
 #[derive(Clone)] // TODO wish this didn't need to be cloneable
@@ -16,6 +16,7 @@ mod bridge_name_tracker;
 pub(crate) mod function_wrapper;
 mod overload_tracker;
 mod rust_name_tracker;
+mod span_constness;
 
 use crate::{
     conversion::{
@@ -27,21 +28,29 @@ use crate::{
 use std::collections::{HashMap, HashSet};
 
 use autocxx_parser::{TypeConfig, UnsafePolicy};
-use function_wrapper::{FunctionWrapper, FunctionWrapperPayload, TypeConversionPolicy};
+use function_wrapper::{
+    CppConversionType, FunctionWrapper, FunctionWrapperPayload, TypeConversionPolicy, UserData,
+    UserDataPosition,
+};
 use proc_macro2::Span;
+use quote::ToTokens;
 use syn::{
-    parse_quote, punctuated::Punctuated, FnArg, ForeignItemFn, Ident, LitStr, Pat, ReturnType,
-    Type, TypePtr, Visibility,
+    parse_quote, punctuated::Punctuated, FnArg, ForeignItemFn, GenericArgument, Ident, Lit, LitStr,
+    Meta, MetaNameValue, Pat, PathArguments, ReturnType, Type, TypePath, TypePtr, TypeReference,
+    Visibility,
 };
 
 use crate::{
     conversion::{
-        api::{Api, ApiAnalysis, ApiDetail, FuncToConvert, TypeKind, UnanalyzedApi},
+        api::{
+            Api, ApiAnalysis, ApiDetail, ConcreteTypeHelpers, FuncToConvert, MapTypes, PairTypes,
+            TypeKind, UnanalyzedApi,
+        },
         codegen_cpp::AdditionalNeed,
         parse::type_converter::TypeConverter,
         ConvertError,
     },
-    types::{make_ident, Namespace, QualifiedName},
+    types::{array_len, make_ident, Namespace, QualifiedName},
 };
 
 use self::{
@@ -83,13 +92,19 @@ pub(crate) struct FnAnalysisBody {
     pub(crate) params: Punctuated<FnArg, syn::Token![,]>,
     pub(crate) kind: FnKind,
     pub(crate) ret_type: ReturnType,
+    pub(crate) ret_conversion: Option<TypeConversionPolicy>,
     pub(crate) param_details: Vec<ArgumentAnalysis>,
     pub(crate) cpp_call_name: String,
     pub(crate) requires_unsafe: bool,
     pub(crate) vis: Visibility,
     pub(crate) cpp_wrapper: Option<AdditionalNeed>,
+    /// Whether the Rust types wrapping any closures passed to this function
+    /// are generated along with another function, as for `make_shared`,
+    /// which shares them with `make_unique`.
+    pub(crate) closures_generated_elsewhere: bool,
 }
 
+#[derive(Clone)]
 pub(crate) struct ArgumentAnalysis {
     pub(crate) conversion: TypeConversionPolicy,
     pub(crate) name: Pat,
@@ -125,6 +140,20 @@ pub(crate) struct FnAnalyzer<'a> {
     incomplete_types: HashSet<QualifiedName>,
     overload_trackers_by_mod: HashMap<Namespace, OverloadTracker>,
     generate_utilities: bool,
+    shared_constructors: Vec<SharedConstructor>,
+    pair_types: HashMap<QualifiedName, PairTypes>,
+}
+
+/// A constructor for which we've generated `make_unique`, and for which
+/// we'll also generate `make_shared`.
+struct SharedConstructor {
+    name: QualifiedName,
+    fun: FuncToConvert,
+    rust_name: String,
+    /// The parameters of `make_unique`, which `make_shared` reuses rather
+    /// than converting them again, so that both share any types and
+    /// helpers which that conversion creates.
+    param_details: Vec<ArgumentAnalysis>,
 }
 
 struct FnAnalysisResult(FnAnalysisBody, Ident, HashSet<QualifiedName>);
@@ -147,12 +176,24 @@ impl<'a> FnAnalyzer<'a> {
             overload_trackers_by_mod: HashMap::new(),
             pod_safe_types: Self::build_pod_safe_type_set(&apis),
             generate_utilities: Self::should_generate_utilities(&apis),
+            shared_constructors: Vec::new(),
+            pair_types: Self::build_pair_types_map(&apis),
         };
         let mut results = Vec::new();
         for api in apis {
             add_api_or_report_error(api.typename(), &mut results, || me.analyze_fn_api(api));
         }
-        results.extend(me.extra_apis.into_iter().map(Self::make_extra_api_nonpod));
+        for constructor in std::mem::take(&mut me.shared_constructors) {
+            add_api_or_report_error(constructor.name.clone(), &mut results, || {
+                me.analyze_shared_constructor(constructor)
+            });
+        }
+        let extra_apis = std::mem::take(&mut me.extra_apis);
+        results.extend(
+            extra_apis
+                .into_iter()
+                .map(|api| me.make_extra_api_nonpod(api)),
+        );
         results
     }
 
@@ -170,12 +211,24 @@ impl<'a> FnAnalyzer<'a> {
             .collect()
     }
 
+    fn build_pair_types_map(apis: &[Api<PodAnalysis>]) -> HashMap<QualifiedName, PairTypes> {
+        apis.iter()
+            .filter_map(|api| match api.detail {
+                ApiDetail::ConcreteType {
+                    helpers: Some(ConcreteTypeHelpers::Pair(ref pair_types)),
+                    ..
+                } => Some((api.typename(), pair_types.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
     fn build_pod_safe_type_set(apis: &[Api<PodAnalysis>]) -> HashSet<QualifiedName> {
         apis.iter()
             .filter_map(|api| match api.detail {
                 ApiDetail::Type {
-                    bindgen_mod_item: _,
                     analysis: TypeKind::Pod,
+                    ..
                 } => Some(api.typename()),
                 _ => None,
             })
@@ -198,9 +251,16 @@ impl<'a> FnAnalyzer<'a> {
     /// Processing functions sometimes results in new types being materialized.
     /// In future, if we wanted to make these POD, we'd probably want to create
     /// a new analysis phase prior to the POD analysis which materializes these types.
-    fn make_extra_api_nonpod(api: UnanalyzedApi) -> Api<FnAnalysis> {
+    fn make_extra_api_nonpod(&self, api: UnanalyzedApi) -> Api<FnAnalysis> {
         let new_detail = match api.detail {
-            ApiDetail::ConcreteType { rs_definition } => ApiDetail::ConcreteType { rs_definition },
+            ApiDetail::ConcreteType {
+                rs_definition,
+                helpers,
+            } => ApiDetail::ConcreteType {
+                rs_definition,
+                helpers: self.analyze_concrete_type_helpers(helpers),
+            },
+            ApiDetail::PrimitiveWrapper { payload } => ApiDetail::PrimitiveWrapper { payload },
             _ => panic!("Function analysis created an extra API which wasn't a concrete type"),
         };
         Api {
@@ -217,14 +277,33 @@ impl<'a> FnAnalyzer<'a> {
         let mut new_deps = api.deps.clone();
         let mut new_id = api.name.get_final_ident();
         let api_detail = match api.detail {
+            ApiDetail::ConcreteType {
+                rs_definition,
+                helpers,
+            } => ApiDetail::ConcreteType {
+                rs_definition,
+                helpers: self.analyze_concrete_type_helpers(helpers),
+            },
             // No changes to any of these...
-            ApiDetail::ConcreteType { rs_definition } => ApiDetail::ConcreteType { rs_definition },
+            ApiDetail::PrimitiveWrapper { payload } => ApiDetail::PrimitiveWrapper { payload },
             ApiDetail::StringConstructor => ApiDetail::StringConstructor,
             ApiDetail::Function { fun, analysis: _ } => {
-                let analysis = self.analyze_foreign_fn(&api.name.get_namespace(), &fun)?;
+                let analysis = self.analyze_foreign_fn(&api.name.get_namespace(), &fun, None)?;
                 match analysis {
                     None => return Ok(None),
                     Some(FnAnalysisResult(analysis, id, fn_deps)) => {
+                        if let FnKind::Method(_, MethodKind::Constructor) = analysis.kind {
+                            self.shared_constructors.push(SharedConstructor {
+                                name: api.name.clone(),
+                                fun: fun.clone(),
+                                rust_name: analysis.rust_name.replacen(
+                                    "make_unique",
+                                    "make_shared",
+                                    1,
+                                ),
+                                param_details: analysis.param_details.clone(),
+                            });
+                        }
                         new_deps = fn_deps;
                         new_id = id;
                         ApiDetail::Function { fun, analysis }
@@ -234,12 +313,16 @@ impl<'a> FnAnalyzer<'a> {
             ApiDetail::Const { const_item } => ApiDetail::Const { const_item },
             ApiDetail::Typedef { payload } => ApiDetail::Typedef { payload },
             ApiDetail::CType { typename } => ApiDetail::CType { typename },
+            ApiDetail::ExternCppType { rust_path } => ApiDetail::ExternCppType { rust_path },
+            ApiDetail::BitfieldUnit { items } => ApiDetail::BitfieldUnit { items },
             // Just changes to this one...
             ApiDetail::Type {
                 bindgen_mod_item,
+                bitfield_accessors,
                 analysis,
             } => ApiDetail::Type {
                 bindgen_mod_item,
+                bitfield_accessors,
                 analysis,
             },
             ApiDetail::ForwardDeclaration => ApiDetail::ForwardDeclaration,
@@ -253,6 +336,53 @@ impl<'a> FnAnalyzer<'a> {
         }))
     }
 
+    /// Now that we know which types are POD, works out whether we can
+    /// marshall a pair into a Rust tuple, and whether a map's values
+    /// can be copied into a Rust map.
+    fn analyze_concrete_type_helpers(
+        &self,
+        helpers: Option<ConcreteTypeHelpers>,
+    ) -> Option<ConcreteTypeHelpers> {
+        match helpers {
+            Some(ConcreteTypeHelpers::Map(map_types)) => Some(ConcreteTypeHelpers::Map(MapTypes {
+                value_is_pod: self.is_pod_safe(&map_types.value),
+                ..map_types
+            })),
+            Some(ConcreteTypeHelpers::Pair(pair_types)) => {
+                Some(ConcreteTypeHelpers::Pair(PairTypes {
+                    marshallable: self.is_marshallable_pair(&pair_types),
+                    ..pair_types
+                }))
+            }
+            _ => helpers,
+        }
+    }
+
+    /// Whether both elements of this pair are POD, primitive or a string.
+    fn is_marshallable_pair(&self, pair_types: &PairTypes) -> bool {
+        [&pair_types.first, &pair_types.second]
+            .iter()
+            .all(|ty| PairTypes::is_string(ty) || self.is_pod_safe(ty))
+    }
+
+    /// Generates `make_shared` for a constructor, alongside the
+    /// `make_unique` which we've already generated.
+    fn analyze_shared_constructor(
+        &mut self,
+        constructor: SharedConstructor,
+    ) -> Result<Option<Api<FnAnalysis>>, ConvertErrorWithContext> {
+        let ns = constructor.name.get_namespace().clone();
+        let analysis = self.analyze_foreign_fn(&ns, &constructor.fun, Some(&constructor))?;
+        Ok(analysis.map(|FnAnalysisResult(analysis, id, deps)| Api {
+            name: QualifiedName::new(&ns, id),
+            deps,
+            detail: ApiDetail::Function {
+                fun: constructor.fun,
+                analysis,
+            },
+        }))
+    }
+
     fn convert_boxed_type(
         &mut self,
         ty: Box<Type>,
@@ -265,6 +395,15 @@ impl<'a> FnAnalyzer<'a> {
             convert_ptrs_to_reference,
             &self.incomplete_types,
         )?;
+        for api in &annotated.extra_apis {
+            if let ApiDetail::ConcreteType {
+                helpers: Some(ConcreteTypeHelpers::Pair(ref pair_types)),
+                ..
+            } = api.detail
+            {
+                self.pair_types.insert(api.typename(), pair_types.clone());
+            }
+        }
         self.extra_apis.extend(annotated.extra_apis);
         Ok((
             annotated.ty,
@@ -291,8 +430,18 @@ impl<'a> FnAnalyzer<'a> {
         self.type_config.is_on_allowlist(&type_name.to_cpp_name())
     }
 
-    fn should_be_unsafe(&self) -> bool {
-        self.unsafe_policy == UnsafePolicy::AllFunctionsUnsafe
+    /// Any Rust name chosen by the user via `rename!` for this C++ item.
+    fn get_user_rename(&self, cpp_name: &str) -> Option<String> {
+        self.type_config.get_rust_name(cpp_name).map(str::to_string)
+    }
+
+    /// Whether a function should be marked `unsafe`. Any per-item
+    /// `safe!` or `unsafe_fn!` directive takes precedence over the
+    /// policy for the whole `include_cpp!` block.
+    fn should_be_unsafe(&self, cpp_name: &str) -> bool {
+        self.type_config
+            .get_unsafe_override(cpp_name)
+            .unwrap_or(self.unsafe_policy == UnsafePolicy::AllFunctionsUnsafe)
     }
 
     /// Determine how to materialize a function.
@@ -310,10 +459,14 @@ impl<'a> FnAnalyzer<'a> {
     /// The other major thing we do here is figure out naming for the function.
     /// This depends on overloads, and what other functions are floating around.
     /// The output of this analysis phase is used by both Rust and C++ codegen.
+    ///
+    /// Constructors are analyzed a second time to generate `make_shared`,
+    /// in which case `shared_constructor` is passed in.
     fn analyze_foreign_fn(
         &mut self,
         ns: &Namespace,
         func_information: &FuncToConvert,
+        shared_constructor: Option<&SharedConstructor>,
     ) -> Result<Option<FnAnalysisResult>, ConvertErrorWithContext> {
         let fun = &func_information.item;
         let virtual_this = &func_information.virtual_this_type;
@@ -332,18 +485,25 @@ impl<'a> FnAnalyzer<'a> {
         // Now let's analyze all the parameters.
         // See if any have annotations which our fork of bindgen has craftily inserted...
         let (reference_params, reference_return) = Self::get_reference_parameters_and_return(&fun);
+        let const_spans = Self::get_const_span_params(fun);
         let (param_details, bads): (Vec<_>, Vec<_>) = fun
             .sig
             .inputs
             .iter()
-            .map(|i| {
-                self.convert_fn_arg(
+            .enumerate()
+            .map(|(counter, i)| match shared_constructor {
+                // Parameters other than `this` were converted for make_unique.
+                Some(constructor) if counter > 0 => {
+                    Ok((i.clone(), constructor.param_details[counter - 1].clone()))
+                }
+                _ => self.convert_fn_arg(
                     i,
                     &ns,
                     diagnostic_display_name,
                     virtual_this.clone(),
                     &reference_params,
-                )
+                    const_spans[counter],
+                ),
             })
             .partition(Result::is_ok);
         let (mut params, mut param_details): (Punctuated<_, syn::Token![,]>, Vec<_>) =
@@ -388,9 +548,6 @@ impl<'a> FnAnalyzer<'a> {
             return Err(contextualize_error(ConvertError::UnusedTemplateParam));
         }
 
-        let requires_unsafe =
-            self.should_be_unsafe() || param_details.iter().any(|pd| pd.requires_unsafe);
-
         // End of parameter processing.
         // Work out naming, part one.
         let mut rust_name;
@@ -419,6 +576,31 @@ impl<'a> FnAnalyzer<'a> {
             (false, self_ty)
         };
 
+        // The fully-qualified C++ name of this function or method, as the user
+        // would refer to it in directives such as `rename!` or `safe!`.
+        let qualified_cpp_name = match self_ty {
+            Some(ref self_ty) => format!("{}::{}", self_ty.to_cpp_name(), cpp_call_name),
+            None => qualify_cpp_name(ns, &cpp_call_name),
+        };
+        // The user may have asked us to combine pairs of pointer and length
+        // parameters into slices.
+        let slice_result =
+            self.fuse_slice_params(&qualified_cpp_name, &mut params, &mut param_details);
+        if let Err(err) = slice_result {
+            return Err(contextualize_error(err));
+        }
+        // Function pointers are accompanied by user data, which we use to
+        // pass the closure.
+        let user_data_result =
+            Self::fuse_user_data_params(&qualified_cpp_name, &mut params, &mut param_details);
+        if let Err(err) = user_data_result {
+            return Err(contextualize_error(err));
+        }
+        // Raw pointer parameters always make a function unsafe, whatever the
+        // user says.
+        let requires_unsafe = self.should_be_unsafe(&qualified_cpp_name)
+            || param_details.iter().any(|pd| pd.requires_unsafe);
+
         let kind = if let Some(self_ty) = self_ty {
             // Some kind of method.
             if !self.is_on_allowlist(&self_ty) {
@@ -430,15 +612,31 @@ impl<'a> FnAnalyzer<'a> {
             }
             // Method or static method.
             let type_ident = self_ty.get_final_item();
+            let is_constructor = ideal_rust_name.starts_with(&type_ident);
+            // The user may have asked for a different Rust name for this method.
+            // Constructors are always called make_unique (or make_shared), below.
+            let ideal_rust_name = if is_constructor {
+                ideal_rust_name
+            } else {
+                self.get_user_rename(&qualified_cpp_name)
+                    .unwrap_or(ideal_rust_name)
+            };
             // bindgen generates methods with the name:
             // {class}_{method name}
             // It then generates an impl section for the Rust type
             // with the original name, but we currently discard that impl section.
             // We want to feed cxx methods with just the method name, so let's
             // strip off the class name.
-            let overload_tracker = self.overload_trackers_by_mod.entry(ns.clone()).or_default();
-            rust_name = overload_tracker.get_method_real_name(&type_ident, ideal_rust_name);
-            let method_kind = if rust_name.starts_with(&type_ident) {
+            rust_name = match shared_constructor {
+                // We already took account of overloads when naming make_unique.
+                Some(constructor) if is_constructor => constructor.rust_name.clone(),
+                _ => {
+                    let overload_tracker =
+                        self.overload_trackers_by_mod.entry(ns.clone()).or_default();
+                    overload_tracker.get_method_real_name(&type_ident, ideal_rust_name)
+                }
+            };
+            let method_kind = if is_constructor {
                 // It's a constructor. bindgen generates
                 // fn new(this: *Type, ...args)
                 // We want
@@ -447,8 +645,10 @@ impl<'a> FnAnalyzer<'a> {
                 // fn make_unique(...args) -> UniquePtr<Type>
                 // If there are multiple constructors, bindgen generates
                 // new, new1, new2 etc. and we'll keep those suffixes.
-                let constructor_suffix = &rust_name[type_ident.len()..];
-                rust_name = format!("make_unique{}", constructor_suffix);
+                if shared_constructor.is_none() {
+                    let constructor_suffix = &rust_name[type_ident.len()..];
+                    rust_name = format!("make_unique{}", constructor_suffix);
+                }
                 // Strip off the 'this' arg.
                 params = params.into_iter().skip(1).collect();
                 param_details.remove(0);
@@ -467,7 +667,11 @@ impl<'a> FnAnalyzer<'a> {
             FnKind::Method(self_ty, method_kind)
         } else {
             // Not a method.
-            // What shall we call this function? It may be overloaded.
+            // What shall we call this function? The user may have renamed it,
+            // and it may be overloaded.
+            let ideal_rust_name = self
+                .get_user_rename(&qualified_cpp_name)
+                .unwrap_or(ideal_rust_name);
             let overload_tracker = self.overload_trackers_by_mod.entry(ns.clone()).or_default();
             rust_name = overload_tracker.get_function_real_name(ideal_rust_name);
             FnKind::Function
@@ -497,9 +701,14 @@ impl<'a> FnAnalyzer<'a> {
                 rt: parse_quote! {
                     -> #constructed_type
                 },
-                conversion: Some(TypeConversionPolicy::new_to_unique_ptr(parse_quote! {
-                    #constructed_type
-                })),
+                conversion: Some(match shared_constructor {
+                    None => TypeConversionPolicy::new_to_unique_ptr(parse_quote! {
+                        #constructed_type
+                    }),
+                    Some(_) => TypeConversionPolicy::new_to_shared_ptr(parse_quote! {
+                        #constructed_type
+                    }),
+                }),
                 was_reference: false,
                 deps: these_deps,
             }
@@ -602,11 +811,19 @@ impl<'a> FnAnalyzer<'a> {
                     #arg_name: #type_name
                 ));
             }
+            if let Some(out_type) = ret_type_conversion
+                .as_ref()
+                .and_then(|conversion| conversion.out_param_rust_type())
+            {
+                params.push(parse_quote!(
+                    autocxx_out: #out_type
+                ));
+            }
 
             Some(AdditionalNeed::FunctionWrapper(Box::new(FunctionWrapper {
                 payload,
                 wrapper_function_name: cxxbridge_name.clone(),
-                return_conversion: ret_type_conversion,
+                return_conversion: ret_type_conversion.clone(),
                 argument_conversion: param_details.iter().map(|d| d.conversion.clone()).collect(),
                 is_a_method: has_receiver,
             })))
@@ -648,11 +865,13 @@ impl<'a> FnAnalyzer<'a> {
                 params,
                 kind,
                 ret_type,
+                ret_conversion: ret_type_conversion,
                 param_details,
                 cpp_call_name,
                 requires_unsafe,
                 vis,
                 cpp_wrapper,
+                closures_generated_elsewhere: shared_constructor.is_some(),
             },
             id,
             deps,
@@ -666,6 +885,7 @@ impl<'a> FnAnalyzer<'a> {
         fn_name: &str,
         virtual_this: Option<QualifiedName>,
         reference_args: &HashSet<Ident>,
+        const_span: bool,
     ) -> Result<(FnArg, ArgumentAnalysis), ConvertError> {
         Ok(match arg {
             FnArg::Typed(pt) => {
@@ -715,15 +935,21 @@ impl<'a> FnAnalyzer<'a> {
                         syn::Pat::Ident(pp)
                     }
                     syn::Pat::Ident(pp) => {
-                        treat_as_reference = reference_args.contains(&pp.ident);
+                        treat_as_reference =
+                            reference_args.contains(&pp.ident) || Self::is_array_pointer(&pt.ty);
                         syn::Pat::Ident(pp)
                     }
                     _ => old_pat,
                 };
                 let (new_ty, deps, requires_unsafe) =
                     self.convert_boxed_type(pt.ty, ns, treat_as_reference)?;
-                let was_reference = matches!(new_ty.as_ref(), Type::Reference(_));
-                let conversion = self.argument_conversion_details(&new_ty);
+                let conversion = match self.callback_conversion(&new_ty, ns, fn_name, &new_pat)? {
+                    Some(conversion) => conversion,
+                    None => self.argument_conversion_details(&new_ty, const_span),
+                };
+                Self::confirm_bridgeable(&conversion.converted_rust_type())?;
+                let was_reference = matches!(new_ty.as_ref(), Type::Reference(_))
+                    || conversion.bridge_type_is_reference();
                 pt.pat = Box::new(new_pat.clone());
                 pt.ty = new_ty;
                 (
@@ -743,8 +969,211 @@ impl<'a> FnAnalyzer<'a> {
         })
     }
 
-    fn argument_conversion_details(&self, ty: &Type) -> TypeConversionPolicy {
+    /// Combines each pair of pointer and length parameters named by a
+    /// `slice!` directive into a single slice parameter.
+    fn fuse_slice_params(
+        &self,
+        qualified_cpp_name: &str,
+        params: &mut Punctuated<FnArg, syn::Token![,]>,
+        param_details: &mut Vec<ArgumentAnalysis>,
+    ) -> Result<(), ConvertError> {
+        for (data_param, len_param) in self.type_config.get_slice_params(qualified_cpp_name) {
+            let find_param = |name: &str| {
+                param_details
+                    .iter()
+                    .position(|pd| matches!(&pd.name, Pat::Ident(pp) if pp.ident == name))
+            };
+            let invalid = || ConvertError::InvalidSliceParams(qualified_cpp_name.to_string());
+            let data_idx = find_param(data_param).ok_or_else(invalid)?;
+            let len_idx = find_param(len_param).ok_or_else(invalid)?;
+            if len_idx != data_idx + 1
+                || !matches!(
+                    param_details[len_idx].conversion.unwrapped_type,
+                    Type::Path(_)
+                )
+            {
+                return Err(invalid());
+            }
+            let slice_type: Type = match &param_details[data_idx].conversion.unwrapped_type {
+                Type::Ptr(TypePtr {
+                    elem, mutability, ..
+                }) if self.is_pod_safe(elem) => parse_quote! {
+                    & #mutability [#elem]
+                },
+                _ => return Err(invalid()),
+            };
+            let data_details = &mut param_details[data_idx];
+            data_details.conversion = TypeConversionPolicy::new_from_slice(slice_type);
+            data_details.was_reference = true;
+            data_details.requires_unsafe = false;
+            param_details.remove(len_idx);
+            *params = std::mem::take(params)
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| *idx != len_idx)
+                .map(|(_, param)| param)
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// Removes the `void*` user data parameter which follows each
+    /// function pointer parameter, since we'll supply it ourselves.
+    fn fuse_user_data_params(
+        qualified_cpp_name: &str,
+        params: &mut Punctuated<FnArg, syn::Token![,]>,
+        param_details: &mut Vec<ArgumentAnalysis>,
+    ) -> Result<(), ConvertError> {
+        let mut idx = 0;
+        while idx < param_details.len() {
+            if matches!(
+                param_details[idx].conversion.cpp_conversion,
+                CppConversionType::FromRefToFnPtrWithUserData(..)
+            ) {
+                let user_data_idx = idx + 1;
+                let user_data_ok = match param_details.get(user_data_idx) {
+                    Some(pd) => Self::void_ptr_is_const(&pd.conversion.unwrapped_type).is_some(),
+                    None => false,
+                };
+                if !user_data_ok {
+                    return Err(ConvertError::UnsupportedCallback(
+                        qualified_cpp_name.to_string(),
+                    ));
+                }
+                param_details.remove(user_data_idx);
+                *params = std::mem::take(params)
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(idx, _)| *idx != user_data_idx)
+                    .map(|(_, param)| param)
+                    .collect();
+            }
+            idx += 1;
+        }
+        Ok(())
+    }
+
+    /// If this parameter is a callback, i.e. a `std::function` or a
+    /// function pointer, works out how to create it from a Rust closure,
+    /// or from a Rust `extern "C"` function if it's a function pointer
+    /// without user data.
+    fn callback_conversion(
+        &mut self,
+        ty: &Type,
+        ns: &Namespace,
+        fn_name: &str,
+        param_name: &Pat,
+    ) -> Result<Option<TypeConversionPolicy>, ConvertError> {
+        let (signature, is_std_function) =
+            match Self::get_template_payload(ty, |tn| known_types().is_function(tn)) {
+                Some(Type::BareFn(bf)) => (bf, true),
+                Some(_) => return Err(ConvertError::UnsupportedCallback(fn_name.to_string())),
+                None => match ty {
+                    Type::BareFn(bf) => (bf, false),
+                    _ => return Ok(None),
+                },
+            };
+        let unsupported = || ConvertError::UnsupportedCallback(fn_name.to_string());
+        let mut arg_types: Vec<Type> = signature.inputs.iter().map(|arg| arg.ty.clone()).collect();
+        let first_is_const = arg_types.first().and_then(Self::void_ptr_is_const);
+        let last_is_const = arg_types.last().and_then(Self::void_ptr_is_const);
+        let user_data = if is_std_function {
+            None
+        } else if let Some(is_const) = first_is_const {
+            arg_types.remove(0);
+            Some(UserData {
+                position: UserDataPosition::First,
+                is_const,
+            })
+        } else if let Some(is_const) = last_is_const {
+            arg_types.pop();
+            Some(UserData {
+                position: UserDataPosition::Last,
+                is_const,
+            })
+        } else {
+            None
+        };
+        let output = &signature.output;
+        if let ReturnType::Type(_, ret_type) = output {
+            if !self.is_pod_safe(ret_type) {
+                return Err(unsupported());
+            }
+        }
+        if !arg_types.iter().all(|ty| self.is_pod_safe(ty)) {
+            return Err(unsupported());
+        }
+        let callback_signature = parse_quote! {
+            fn( #(#arg_types),* ) #output
+        };
+        if !is_std_function && user_data.is_none() {
+            return Ok(Some(TypeConversionPolicy::new_fn_ptr_from_extern_fn(
+                callback_signature,
+            )));
+        }
+        let param_name = match param_name {
+            Pat::Ident(pp) => pp.ident.to_string(),
+            _ => return Err(unsupported()),
+        };
+        let closure_type = make_ident(self.get_cxx_bridge_name(
+            None,
+            &format!("{}_{}_closure", fn_name, param_name),
+            ns,
+        ));
+        Ok(Some(match user_data {
+            None => TypeConversionPolicy::new_std_function_from_closure(
+                callback_signature,
+                closure_type,
+            ),
+            Some(user_data) => TypeConversionPolicy::new_fn_ptr_from_closure(
+                callback_signature,
+                closure_type,
+                user_data,
+            ),
+        }))
+    }
+
+    /// If this is a `void*` or a `const void*`, whether it's `const`.
+    fn void_ptr_is_const(ty: &Type) -> Option<bool> {
+        match ty {
+            Type::Ptr(TypePtr {
+                elem, mutability, ..
+            }) => match elem.as_ref() {
+                Type::Path(typ) if QualifiedName::from_type_path(typ).is_cvoid() => {
+                    Some(mutability.is_none())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn argument_conversion_details(&self, ty: &Type, const_span: bool) -> TypeConversionPolicy {
+        if let Some(payload) = Self::get_optional_payload(ty) {
+            return if self.is_pod_safe(payload) {
+                TypeConversionPolicy::new_optional_from_ptr(payload.clone())
+            } else {
+                TypeConversionPolicy::new_optional_from_unique_ptr(payload.clone())
+            };
+        }
+        if Self::is_string_view(ty) {
+            return TypeConversionPolicy::new_string_view_from_str();
+        }
+        if let Some(payload) =
+            Self::get_span_payload(ty).filter(|payload| self.is_pod_safe(payload))
+        {
+            return TypeConversionPolicy::new_span_from_slice(if const_span {
+                parse_quote! { &[#payload] }
+            } else {
+                parse_quote! { &mut [#payload] }
+            });
+        }
         match ty {
+            Type::Reference(TypeReference { elem, .. })
+                if matches!(elem.as_ref(), Type::Array(_)) =>
+            {
+                TypeConversionPolicy::new_from_std_array(ty.clone())
+            }
             Type::Path(p) => {
                 let tn = QualifiedName::from_type_path(p);
                 if self.pod_safe_types.contains(&tn) {
@@ -760,19 +1189,141 @@ impl<'a> FnAnalyzer<'a> {
     }
 
     fn return_type_conversion_details(&self, ty: &Type) -> TypeConversionPolicy {
+        if let Some(payload) = Self::get_optional_payload(ty) {
+            return if self.is_pod_safe(payload) {
+                TypeConversionPolicy::new_optional_to_out_ptr(payload.clone())
+            } else {
+                TypeConversionPolicy::new_optional_to_unique_ptr(payload.clone())
+            };
+        }
+        if Self::is_string_view(ty) {
+            return TypeConversionPolicy::new_string_view_to_str();
+        }
         match ty {
             Type::Path(p) => {
                 let tn = QualifiedName::from_type_path(p);
                 if self.pod_safe_types.contains(&tn) {
-                    TypeConversionPolicy::new_unconverted(ty.clone())
-                } else {
-                    TypeConversionPolicy::new_to_unique_ptr(ty.clone())
+                    return TypeConversionPolicy::new_unconverted(ty.clone());
+                }
+                match self.pair_types.get(&tn) {
+                    Some(pair_types) if self.is_marshallable_pair(pair_types) => {
+                        TypeConversionPolicy::new_pair_to_struct(ty.clone(), pair_types)
+                    }
+                    _ => TypeConversionPolicy::new_to_unique_ptr(ty.clone()),
                 }
             }
             _ => TypeConversionPolicy::new_unconverted(ty.clone()),
         }
     }
 
+    /// Whether this is a pointer to an array of known size, which is how
+    /// bindgen represents array parameters such as `uint32_t a[4]`.
+    fn is_array_pointer(ty: &Type) -> bool {
+        match ty {
+            Type::Ptr(TypePtr { elem, .. }) => match elem.as_ref() {
+                Type::Array(arr) => matches!(array_len(arr), Some(len) if len > 0),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// If this type is passed by value or by const reference, returns
+    /// the path of the underlying type.
+    fn get_by_value_or_const_ref(ty: &Type) -> Option<&TypePath> {
+        match ty {
+            Type::Path(typ) => Some(typ),
+            Type::Reference(TypeReference {
+                elem,
+                mutability: None,
+                ..
+            }) => match elem.as_ref() {
+                Type::Path(typ) => Some(typ),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// If this type is a `std::optional<T>` (now an `Option<T>`), passed
+    /// by value or by const reference, returns `T`.
+    fn get_optional_payload(ty: &Type) -> Option<&Type> {
+        Self::get_template_payload(ty, |tn| known_types().is_optional(tn))
+    }
+
+    /// If this type is a `std::span<T>`, passed by value or by const
+    /// reference, returns `T`.
+    fn get_span_payload(ty: &Type) -> Option<&Type> {
+        Self::get_template_payload(ty, |tn| known_types().is_span(tn))
+    }
+
+    fn get_template_payload(
+        ty: &Type,
+        is_template: impl Fn(&QualifiedName) -> bool,
+    ) -> Option<&Type> {
+        let typ = Self::get_by_value_or_const_ref(ty)?;
+        if !is_template(&QualifiedName::from_type_path(typ)) {
+            return None;
+        }
+        match &typ.path.segments.last()?.arguments {
+            PathArguments::AngleBracketed(ab) => match ab.args.first() {
+                Some(GenericArgument::Type(payload)) => Some(payload),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether this type is a `std::string_view`, passed by value or by
+    /// const reference.
+    fn is_string_view(ty: &Type) -> bool {
+        Self::get_by_value_or_const_ref(ty)
+            .map(|typ| known_types().is_string_view(&QualifiedName::from_type_path(typ)))
+            .unwrap_or(false)
+    }
+
+    /// Types such as `std::optional` can only be handled by our wrapper
+    /// functions in certain positions. Checks that none remain in
+    /// a type which is about to be given to cxx.
+    fn confirm_bridgeable(ty: &Type) -> Result<(), ConvertError> {
+        match ty {
+            Type::Path(typ) => {
+                if known_types().lacks_cxx_equivalent(&QualifiedName::from_type_path(typ)) {
+                    return Err(ConvertError::UnsupportedType(
+                        ty.to_token_stream().to_string(),
+                    ));
+                }
+                if let Some(PathArguments::AngleBracketed(ab)) =
+                    typ.path.segments.last().map(|seg| &seg.arguments)
+                {
+                    for arg in &ab.args {
+                        if let GenericArgument::Type(inner) = arg {
+                            Self::confirm_bridgeable(inner)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Type::Reference(TypeReference { elem, .. }) | Type::Ptr(TypePtr { elem, .. }) => {
+                Self::confirm_bridgeable(elem)
+            }
+            // Function pointers are only acceptable as callback parameters.
+            Type::BareFn(_) => Err(ConvertError::UnsupportedType(
+                ty.to_token_stream().to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_pod_safe(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Path(typ) => self
+                .pod_safe_types
+                .contains(&QualifiedName::from_type_path(typ)),
+            _ => false,
+        }
+    }
+
     fn convert_return_type(
         &mut self,
         rt: &ReturnType,
@@ -790,8 +1341,10 @@ impl<'a> FnAnalyzer<'a> {
                 // TODO remove the below clone
                 let (boxed_type, deps, _) =
                     self.convert_boxed_type(boxed_type.clone(), ns, convert_ptr_to_reference)?;
-                let was_reference = matches!(boxed_type.as_ref(), Type::Reference(_));
                 let conversion = self.return_type_conversion_details(boxed_type.as_ref());
+                Self::confirm_bridgeable(&conversion.unconverted_rust_type())?;
+                let was_reference = matches!(boxed_type.as_ref(), Type::Reference(_))
+                    || conversion.bridge_type_is_reference();
                 ReturnTypeAnalysis {
                     rt: ReturnType::Type(*rarrow, boxed_type),
                     conversion: Some(conversion),
@@ -836,21 +1389,60 @@ impl<'a> FnAnalyzer<'a> {
         (ref_params, ref_return)
     }
 
+    /// For each parameter, whether it's a `std::span` with const elements.
+    /// If we can't tell, we have to assume the elements may be mutated.
+    fn get_const_span_params(fun: &ForeignItemFn) -> Vec<bool> {
+        let is_this = |arg: &FnArg| matches!(arg, FnArg::Typed(pt) if matches!(pt.pat.as_ref(), syn::Pat::Ident(pp) if pp.ident == "this"));
+        let mut const_spans = Self::get_link_name(fun)
+            .and_then(|link_name| span_constness::const_span_params(&link_name))
+            .filter(|params| {
+                params.len() == fun.sig.inputs.iter().filter(|arg| !is_this(arg)).count()
+            })
+            .unwrap_or_default()
+            .into_iter();
+        fun.sig
+            .inputs
+            .iter()
+            .map(|arg| !is_this(arg) && const_spans.next().unwrap_or(false))
+            .collect()
+    }
+
+    fn get_link_name(fun: &ForeignItemFn) -> Option<String> {
+        fun.attrs
+            .iter()
+            .filter(|a| a.path.is_ident("link_name"))
+            .filter_map(|a| match a.parse_meta() {
+                Ok(Meta::NameValue(MetaNameValue {
+                    lit: Lit::Str(ls), ..
+                })) => Some(ls.value()),
+                _ => None,
+            })
+            .next()
+    }
+
     fn has_attr(fun: &ForeignItemFn, attr_name: &str) -> bool {
         fun.attrs.iter().any(|at| at.path.is_ident(attr_name))
     }
 }
 
 impl Api<FnAnalysis> {
-    pub(crate) fn typename_for_allowlist(&self) -> QualifiedName {
+    /// The C++ names which may have been listed on the allowlist in order
+    /// to cause this API to be generated. Functions may have been requested
+    /// either by their Rust name or (if the user renamed them) by their
+    /// C++ name.
+    pub(crate) fn names_for_allowlist(&self) -> Vec<String> {
         match &self.detail {
             ApiDetail::Function { fun: _, analysis } => match analysis.kind {
-                FnKind::Method(ref self_ty, _) => self_ty.clone(),
+                FnKind::Method(ref self_ty, _) => vec![self_ty.to_cpp_name()],
                 FnKind::Function => {
-                    QualifiedName::new(&self.name.get_namespace(), make_ident(&analysis.rust_name))
+                    let ns = self.name.get_namespace();
+                    vec![
+                        QualifiedName::new(ns, make_ident(&analysis.rust_name)).to_cpp_name(),
+                        qualify_cpp_name(ns, &analysis.cpp_call_name),
+                    ]
                 }
             },
-            _ => self.typename(),
+            _ => vec![self.typename().to_cpp_name()],
         }
     }
 
@@ -864,10 +1456,19 @@ impl Api<FnAnalysis> {
         match &self.detail {
             ApiDetail::Function { fun: _, analysis } => analysis.cpp_wrapper.clone(),
             ApiDetail::StringConstructor => Some(AdditionalNeed::MakeStringConstructor),
-            ApiDetail::ConcreteType { rs_definition } => {
+            ApiDetail::ConcreteType {
+                rs_definition,
+                helpers,
+            } => Some(AdditionalNeed::ConcreteTemplatedTypeTypedef(
+                self.name.clone(),
+                rs_definition.clone(),
+                helpers.clone(),
+            )),
+            ApiDetail::PrimitiveWrapper { payload } => {
                 Some(AdditionalNeed::ConcreteTemplatedTypeTypedef(
                     self.name.clone(),
-                    rs_definition.clone(),
+                    payload.clone(),
+                    None,
                 ))
             }
             ApiDetail::CType { typename } => Some(AdditionalNeed::CTypeTypedef(typename.clone())),
@@ -875,3 +1476,14 @@ impl Api<FnAnalysis> {
         }
     }
 }
+
+/// The fully-qualified C++ name of a function, given its unqualified
+/// C++ name. The name may not be a valid Rust identifier (e.g. an operator)
+/// so we don't go via [QualifiedName].
+fn qualify_cpp_name(ns: &Namespace, name: &str) -> String {
+    if ns.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", ns, name)
+    }
+}
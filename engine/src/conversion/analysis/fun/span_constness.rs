@@ -0,0 +1,517 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// What we need to know about each type spelt out in a mangled name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MangledType {
+    /// The `std::span` template itself, before its arguments.
+    SpanTemplate,
+    /// A `std::span`, or a pointer or reference to one.
+    Span { const_elems: bool },
+    /// Anything else.
+    Other { is_const: bool },
+}
+
+impl MangledType {
+    fn qualified(self, is_const: bool) -> Self {
+        match self {
+            MangledType::Other { .. } => MangledType::Other { is_const },
+            _ => self,
+        }
+    }
+
+    fn pointer_to(self) -> Self {
+        match self {
+            MangledType::Span { .. } => self,
+            _ => MangledType::Other { is_const: false },
+        }
+    }
+}
+
+/// bindgen drops the `const` from template arguments, so `std::span<const T>`
+/// and `std::span<T>` look the same in its output. They're distinct in
+/// the Itanium mangled name, though, so this walks the parameters there.
+/// For each parameter (not counting `this`), returns whether it's a
+/// `std::span` (by value, pointer or reference) with const elements.
+/// Returns `None` for any name we can't fully understand, including
+/// all MSVC names.
+pub(crate) fn const_span_params(link_name: &str) -> Option<Vec<bool>> {
+    // bindgen marks names which mustn't be mangled any further with `\u{1}`.
+    let link_name = link_name.trim_start_matches('\u{1}');
+    let mangled = link_name
+        .strip_prefix("_Z")
+        .or_else(|| link_name.strip_prefix("__Z"))?;
+    // Ignore any clone suffix such as `.constprop.0`.
+    let mangled = mangled.split('.').next()?;
+    let mut parser = Parser {
+        input: mangled.as_bytes(),
+        pos: 0,
+        substitutions: Vec::new(),
+    };
+    let is_template = parser.function_name()?;
+    if &parser.input[parser.pos..] == b"v" {
+        // No parameters.
+        return Some(Vec::new());
+    }
+    let mut params = Vec::new();
+    while !parser.at_end() {
+        params.push(parser.ty()?);
+    }
+    if is_template {
+        // Template functions have their return type first.
+        if params.is_empty() {
+            return None;
+        }
+        params.remove(0);
+    }
+    Some(
+        params
+            .into_iter()
+            .map(|p| matches!(p, MangledType::Span { const_elems: true }))
+            .collect(),
+    )
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    substitutions: Vec<MangledType>,
+}
+
+impl<'a> Parser<'a> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn consume(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn add_substitution(&mut self, ty: MangledType) -> MangledType {
+        self.substitutions.push(ty);
+        ty
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn source_name(&mut self) -> Option<&'a str> {
+        let len = self.number()?;
+        let name = self.input.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        std::str::from_utf8(name).ok()
+    }
+
+    /// The name of the function itself. Returns whether it's a template.
+    fn function_name(&mut self) -> Option<bool> {
+        match self.peek()? {
+            b'N' => self.nested_name(true).map(|(_, is_template)| is_template),
+            b'S' if self.peek_at(1) == Some(b't') => {
+                self.pos += 2;
+                self.unscoped_name(true, true).map(|(_, t)| t)
+            }
+            b'Z' => None,
+            _ => self.unscoped_name(false, true).map(|(_, t)| t),
+        }
+    }
+
+    /// A name which isn't nested, optionally followed by template arguments.
+    fn unscoped_name(&mut self, in_std: bool, is_function: bool) -> Option<(MangledType, bool)> {
+        let name = self.unqualified_name()?;
+        let mut ty = if in_std && name == Some("span") {
+            MangledType::SpanTemplate
+        } else {
+            MangledType::Other { is_const: false }
+        };
+        let is_template = self.peek() == Some(b'I');
+        if is_template {
+            self.add_substitution(ty);
+            ty = self.template_args(ty)?;
+        }
+        if !is_function {
+            self.add_substitution(ty);
+        }
+        Some((ty, is_template))
+    }
+
+    /// Returns the identifier, if this is a simple name rather than an
+    /// operator, constructor or destructor.
+    fn unqualified_name(&mut self) -> Option<Option<&'a str>> {
+        self.consume(b'L');
+        let name = match self.peek()? {
+            b'0'..=b'9' => Some(self.source_name()?),
+            b'C' if matches!(self.peek_at(1), Some(b'1'..=b'5')) => {
+                self.pos += 2;
+                None
+            }
+            b'D' if matches!(self.peek_at(1), Some(b'0'..=b'2')) => {
+                self.pos += 2;
+                None
+            }
+            b'c' if self.peek_at(1) == Some(b'v') => {
+                self.pos += 2;
+                self.ty()?;
+                None
+            }
+            b'a'..=b'z' => {
+                self.pos += 2;
+                None
+            }
+            _ => return None,
+        };
+        // ABI tags, e.g. `B5cxx11`.
+        while self.consume(b'B') {
+            self.source_name()?;
+        }
+        Some(name)
+    }
+
+    /// `N [qualifiers] prefix... E`. Returns the type and whether the
+    /// last component was a list of template arguments.
+    fn nested_name(&mut self, is_function: bool) -> Option<(MangledType, bool)> {
+        self.pos += 1;
+        while matches!(self.peek()?, b'r' | b'V' | b'K' | b'R' | b'O') {
+            self.pos += 1;
+        }
+        let mut in_std = false;
+        let mut current = None;
+        let mut is_template = false;
+        loop {
+            match self.peek()? {
+                b'E' => {
+                    self.pos += 1;
+                    return current.map(|ty| (ty, is_template));
+                }
+                b'S' if self.peek_at(1) == Some(b't') && current.is_none() => {
+                    self.pos += 2;
+                    in_std = true;
+                    continue;
+                }
+                b'S' if current.is_none() => {
+                    current = Some(self.substitution()?);
+                    continue;
+                }
+                b'I' => {
+                    current = Some(self.template_args(current?)?);
+                    is_template = true;
+                }
+                b'T' => {
+                    self.template_param()?;
+                    current = Some(MangledType::Other { is_const: false });
+                    is_template = false;
+                }
+                _ => {
+                    is_template = false;
+                    let name = self.unqualified_name()?;
+                    // Inline namespaces such as libc++'s `std::__1`
+                    // don't stop this being `std::span`.
+                    let only_std_so_far = in_std
+                        && current.is_none()
+                        && name.map(|n| n.starts_with("__")).unwrap_or(false);
+                    current = Some(if in_std && name == Some("span") {
+                        MangledType::SpanTemplate
+                    } else {
+                        MangledType::Other { is_const: false }
+                    });
+                    if only_std_so_far {
+                        self.add_substitution(current?);
+                        current = None;
+                        continue;
+                    }
+                }
+            }
+            in_std = false;
+            // Every prefix is a substitution candidate, except the name
+            // of the function itself.
+            if !(is_function && self.peek() == Some(b'E')) {
+                self.add_substitution(current?);
+            }
+        }
+    }
+
+    fn substitution(&mut self) -> Option<MangledType> {
+        self.pos += 1;
+        match self.next()? {
+            b'_' => self.substitutions.first().copied(),
+            b'a' | b'b' | b's' | b'i' | b'o' | b'd' => Some(MangledType::Other { is_const: false }),
+            c @ (b'0'..=b'9' | b'A'..=b'Z') => {
+                let mut index = 0usize;
+                let mut c = c;
+                loop {
+                    let digit = match c {
+                        b'0'..=b'9' => c - b'0',
+                        b'A'..=b'Z' => c - b'A' + 10,
+                        b'_' => break,
+                        _ => return None,
+                    };
+                    index = index * 36 + digit as usize;
+                    c = self.next()?;
+                }
+                self.substitutions.get(index + 1).copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn template_param(&mut self) -> Option<()> {
+        self.pos += 1;
+        while self.next()? != b'_' {}
+        Some(())
+    }
+
+    /// `I arg... E`, applied to the given template.
+    fn template_args(&mut self, template: MangledType) -> Option<MangledType> {
+        self.pos += 1;
+        let mut first = None;
+        while !self.consume(b'E') {
+            let arg = match self.peek()? {
+                b'L' => {
+                    // A literal, such as the extent of a `std::span`.
+                    self.pos += 1;
+                    if self.peek()? == b'_' {
+                        return None;
+                    }
+                    self.ty()?;
+                    while self.next()? != b'E' {}
+                    MangledType::Other { is_const: false }
+                }
+                b'J' => {
+                    self.pos += 1;
+                    while !self.consume(b'E') {
+                        self.ty()?;
+                    }
+                    MangledType::Other { is_const: false }
+                }
+                b'X' => return None,
+                _ => self.ty()?,
+            };
+            first.get_or_insert(arg);
+        }
+        Some(match template {
+            MangledType::SpanTemplate => MangledType::Span {
+                const_elems: matches!(first, Some(MangledType::Other { is_const: true })),
+            },
+            _ => MangledType::Other { is_const: false },
+        })
+    }
+
+    fn ty(&mut self) -> Option<MangledType> {
+        let other = MangledType::Other { is_const: false };
+        Some(match self.peek()? {
+            b'v' | b'w' | b'b' | b'c' | b'a' | b'h' | b's' | b't' | b'i' | b'j' | b'l' | b'm'
+            | b'x' | b'y' | b'n' | b'o' | b'f' | b'd' | b'e' | b'g' | b'z' => {
+                self.pos += 1;
+                other
+            }
+            b'u' => {
+                self.pos += 1;
+                self.source_name()?;
+                self.add_substitution(other)
+            }
+            b'D' => match self.peek_at(1)? {
+                b'd' | b'e' | b'f' | b'h' | b'i' | b's' | b'u' | b'a' | b'c' | b'n' => {
+                    self.pos += 2;
+                    other
+                }
+                b'p' => {
+                    self.pos += 2;
+                    self.ty()?;
+                    self.add_substitution(other)
+                }
+                _ => return None,
+            },
+            b'r' | b'V' | b'K' => {
+                let mut is_const = false;
+                while matches!(self.peek()?, b'r' | b'V' | b'K') {
+                    is_const |= self.next()? == b'K';
+                }
+                let inner = self.ty()?;
+                self.add_substitution(inner.qualified(is_const))
+            }
+            b'P' | b'R' | b'O' => {
+                self.pos += 1;
+                let inner = self.ty()?;
+                self.add_substitution(inner.pointer_to())
+            }
+            b'C' | b'G' => {
+                self.pos += 1;
+                self.ty()?;
+                self.add_substitution(other)
+            }
+            b'F' => {
+                self.pos += 1;
+                self.consume(b'Y');
+                while !self.consume(b'E') {
+                    if matches!(self.peek()?, b'R' | b'O') && self.peek_at(1)? == b'E' {
+                        self.pos += 1;
+                        continue;
+                    }
+                    self.ty()?;
+                }
+                self.add_substitution(other)
+            }
+            b'A' => {
+                self.pos += 1;
+                if self.peek()? != b'_' {
+                    self.number()?;
+                }
+                if !self.consume(b'_') {
+                    return None;
+                }
+                self.ty()?;
+                self.add_substitution(other)
+            }
+            b'M' => {
+                self.pos += 1;
+                self.ty()?;
+                self.ty()?;
+                self.add_substitution(other)
+            }
+            b'T' => {
+                self.template_param()?;
+                self.add_substitution(other)
+            }
+            b'S' if self.peek_at(1) == Some(b't') => {
+                self.pos += 2;
+                self.unscoped_name(true, false)?.0
+            }
+            b'S' => {
+                let ty = self.substitution()?;
+                if self.peek() == Some(b'I') {
+                    let ty = self.template_args(ty)?;
+                    self.add_substitution(ty)
+                } else {
+                    ty
+                }
+            }
+            b'N' => self.nested_name(false)?.0,
+            b'0'..=b'9' => self.unscoped_name(false, false)?.0,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::const_span_params;
+
+    const EXTENT: &str = "Lm18446744073709551615E";
+
+    #[test]
+    fn test_const_and_mutable_spans() {
+        // void f(std::span<const int>, std::span<int>)
+        let name = format!("_Z1fSt4spanIKi{0}ES_Ii{0}E", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true, false]));
+        // void f(std::span<int>, std::span<const int>)
+        let name = format!("_Z1fSt4spanIi{0}ES_IKi{0}E", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![false, true]));
+    }
+
+    #[test]
+    fn test_repeated_span() {
+        // void f(std::span<const int>, std::span<const int>)
+        let name = format!("_Z1fSt4spanIKi{}ES1_", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true, true]));
+    }
+
+    #[test]
+    fn test_method_with_other_params() {
+        // void ns::A::f(const std::span<const uint8_t>&, int) const
+        let name = format!("_ZNK2ns1A1fERKSt4spanIKh{}Ei", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true, false]));
+    }
+
+    #[test]
+    fn test_substituted_elements() {
+        // void f(std::string, std::span<const std::string>, std::span<std::string>)
+        let name = format!(
+            "_Z1fNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEESt4spanIKS4_{0}ES5_IS4_{0}E",
+            EXTENT
+        );
+        assert_eq!(const_span_params(&name), Some(vec![false, true, false]));
+        // void ns::A::g(std::span<int>, std::span<const int>, std::span<const int>)
+        let name = format!("_ZN2ns1A1gESt4spanIi{0}ES1_IKi{0}ES4_", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![false, true, true]));
+    }
+
+    #[test]
+    fn test_pointers_and_references() {
+        // void f(const std::span<const double>*, std::span<double>&)
+        let name = format!("_Z1fPKSt4spanIKd{0}ERS_Id{0}E", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true, false]));
+    }
+
+    #[test]
+    fn test_constructor() {
+        // ns::A::A(std::span<const char>)
+        let name = format!("_ZN2ns1AC1ESt4spanIKc{}E", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true]));
+    }
+
+    #[test]
+    fn test_libcxx_span() {
+        // void f(std::__1::span<const int>)
+        let name = format!("_Z1fNSt3__14spanIKi{}EE", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true]));
+    }
+
+    #[test]
+    fn test_user_type_named_span() {
+        // void f(ns::span<const int>)
+        assert_eq!(const_span_params("_Z1fN2ns4spanIKiEE"), Some(vec![false]));
+    }
+
+    #[test]
+    fn test_bindgen_link_name() {
+        let name = format!("\u{1}_Z1fSt4spanIKi{}E", EXTENT);
+        assert_eq!(const_span_params(&name), Some(vec![true]));
+    }
+
+    #[test]
+    fn test_no_params() {
+        assert_eq!(const_span_params("_Z1fv"), Some(vec![]));
+    }
+
+    #[test]
+    fn test_msvc() {
+        assert_eq!(const_span_params("?f@@YAXV?$span@$$CBH$0?0@std@@@Z"), None);
+    }
+}
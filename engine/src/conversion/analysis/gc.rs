@@ -44,8 +44,9 @@ pub(crate) fn filter_apis_by_following_edges_from_allowlist(
     let mut todos: Vec<_> = apis
         .iter()
         .filter(|api| {
-            let tnforal = api.typename_for_allowlist();
-            type_config.is_on_allowlist(&tnforal.to_cpp_name())
+            api.names_for_allowlist()
+                .iter()
+                .any(|name| type_config.is_on_allowlist(name))
         })
         .map(Api::typename)
         .collect();
@@ -25,7 +25,7 @@ use crate::{
 };
 use autocxx_parser::TypeConfig;
 use std::collections::HashMap;
-use syn::{Item, ItemStruct, Type};
+use syn::{Field, Item, ItemStruct, ItemUnion, Type};
 
 #[derive(Clone)]
 enum PodState {
@@ -90,6 +90,16 @@ impl ByValueChecker {
                 .insert(tn, StructDetails::new(safety));
         }
         for api in apis {
+            let name = api.typename();
+            if type_config.is_on_blocklist(&name.to_cpp_name()) {
+                // Catches anything matching a block_pattern! as well as
+                // the explicit blocklist entries above.
+                let safety = PodState::UnsafeToBePod(format!("type {} is on the blocklist", &name));
+                byvalue_checker
+                    .results
+                    .insert(name, StructDetails::new(safety));
+                continue;
+            }
             match &api.detail {
                 ApiDetail::Typedef { payload } => {
                     let name = api.typename();
@@ -117,12 +127,16 @@ impl ByValueChecker {
                 }
                 ApiDetail::Type {
                     bindgen_mod_item,
+                    bitfield_accessors: _,
                     analysis: _,
                 } => match bindgen_mod_item {
                     None => {}
                     Some(Item::Struct(s)) => {
                         byvalue_checker.ingest_struct(&s, &api.name.get_namespace())
                     }
+                    Some(Item::Union(u)) => {
+                        byvalue_checker.ingest_union(u, api.name.get_namespace())
+                    }
                     Some(Item::Enum(_)) => {
                         byvalue_checker
                             .results
@@ -131,6 +145,21 @@ impl ByValueChecker {
                     _ => {}
                 },
                 ApiDetail::OpaqueTypedef => byvalue_checker.ingest_nonpod_type(api.typename()),
+                // Bitfields are just bytes, so don't stop a struct being POD.
+                ApiDetail::BitfieldUnit { .. } => {
+                    byvalue_checker
+                        .results
+                        .insert(api.typename(), StructDetails::new(PodState::IsPod));
+                }
+                ApiDetail::ExternCppType { .. } => {
+                    // We don't know how the other bindings chose to represent
+                    // this type, so we can't assume it's POD.
+                    let name = api.typename();
+                    let reason = format!("Type {} is bound elsewhere by extern_cpp_type", name);
+                    byvalue_checker
+                        .results
+                        .insert(name, StructDetails::new(PodState::UnsafeToBePod(reason)));
+                }
                 _ => {}
             }
         }
@@ -148,8 +177,32 @@ impl ByValueChecker {
     fn ingest_struct(&mut self, def: &ItemStruct, ns: &Namespace) {
         // For this struct, work out whether it _could_ be safe as a POD.
         let tyname = QualifiedName::new(ns, def.ident.clone());
+        let field_safety_problem = if Self::has_vtable(def) {
+            Some(format!(
+                "Type {} could not be POD because it has virtual functions.",
+                tyname
+            ))
+        } else {
+            None
+        };
+        self.ingest_fields(tyname, def.fields.iter(), field_safety_problem);
+    }
+
+    fn ingest_union(&mut self, def: &ItemUnion, ns: &Namespace) {
+        // A union is POD if all its members are. Rust code then
+        // needs `unsafe` to read any given member.
+        let tyname = QualifiedName::new(ns, def.ident.clone());
+        self.ingest_fields(tyname, def.fields.named.iter(), None);
+    }
+
+    fn ingest_fields<'a>(
+        &mut self,
+        tyname: QualifiedName,
+        fields: impl Iterator<Item = &'a Field>,
+        other_problem: Option<String>,
+    ) {
         let mut field_safety_problem = PodState::SafeToBePod;
-        let fieldlist = Self::get_field_types(def);
+        let fieldlist = Self::get_field_types(fields);
         for ty_id in &fieldlist {
             match self.results.get(ty_id) {
                 None => {
@@ -168,11 +221,7 @@ impl ByValueChecker {
                 }
             }
         }
-        if Self::has_vtable(def) {
-            let reason = format!(
-                "Type {} could not be POD because it has virtual functions.",
-                tyname
-            );
+        if let Some(reason) = other_problem {
             field_safety_problem = PodState::UnsafeToBePod(reason);
         }
         let mut my_details = StructDetails::new(field_safety_problem);
@@ -241,14 +290,18 @@ impl ByValueChecker {
         )
     }
 
-    fn get_field_types(def: &ItemStruct) -> Vec<QualifiedName> {
+    fn get_field_types<'a>(fields: impl Iterator<Item = &'a Field>) -> Vec<QualifiedName> {
         let mut results = Vec::new();
-        for f in &def.fields {
-            let fty = &f.ty;
+        for f in fields {
+            let mut fty = &f.ty;
+            // An array is POD if its elements are.
+            while let Type::Array(arr) = fty {
+                fty = &arr.elem;
+            }
             if let Type::Path(p) = fty {
                 results.push(QualifiedName::from_type_path(&p));
             }
-            // TODO handle anything else which bindgen might spit out, e.g. arrays?
+            // TODO handle anything else which bindgen might spit out?
         }
         results
     }
@@ -267,7 +320,7 @@ impl ByValueChecker {
 mod tests {
     use super::ByValueChecker;
     use crate::types::{Namespace, QualifiedName};
-    use syn::{parse_quote, Ident, ItemStruct};
+    use syn::{parse_quote, Ident, ItemStruct, ItemUnion};
 
     fn ty_from_ident(id: &Ident) -> QualifiedName {
         QualifiedName::new_from_user_input(&id.to_string())
@@ -345,4 +398,70 @@ mod tests {
         bvc.ingest_struct(&t, &Namespace::new());
         assert!(bvc.satisfy_requests(vec![t_id]).is_err());
     }
+
+    #[test]
+    fn test_with_arrays() {
+        let mut bvc = ByValueChecker::new();
+        let t: ItemStruct = parse_quote! {
+            struct Foo {
+                a: [i32; 4usize],
+                b: [[u8; 2usize]; 3usize],
+            }
+        };
+        let t_id = ty_from_ident(&t.ident);
+        bvc.ingest_struct(&t, &Namespace::new());
+        bvc.satisfy_requests(vec![t_id.clone()]).unwrap();
+        assert!(bvc.is_pod(&t_id));
+    }
+
+    #[test]
+    fn test_with_array_of_cxxstring() {
+        let mut bvc = ByValueChecker::new();
+        let t: ItemStruct = parse_quote! {
+            struct Bar {
+                a: [cxx::CxxString; 2usize],
+            }
+        };
+        let t_id = ty_from_ident(&t.ident);
+        bvc.ingest_struct(&t, &Namespace::new());
+        assert!(bvc.satisfy_requests(vec![t_id]).is_err());
+    }
+
+    #[test]
+    fn test_struct_containing_union() {
+        let mut bvc = ByValueChecker::new();
+        let u: ItemUnion = parse_quote! {
+            union Foo {
+                a: u32,
+                b: f32,
+            }
+        };
+        let u_id = ty_from_ident(&u.ident);
+        bvc.ingest_union(&u, &Namespace::new());
+        let t: ItemStruct = parse_quote! {
+            struct Bar {
+                a: Foo,
+                b: i64,
+            }
+        };
+        let t_id = ty_from_ident(&t.ident);
+        bvc.ingest_struct(&t, &Namespace::new());
+        bvc.satisfy_requests(vec![t_id.clone()]).unwrap();
+        assert!(bvc.is_pod(&t_id));
+        assert!(bvc.is_pod(&u_id));
+    }
+
+    #[test]
+    fn test_union_with_cxxstring() {
+        let mut bvc = ByValueChecker::new();
+        let u: ItemUnion = parse_quote! {
+            union Foo {
+                a: CxxString,
+                b: u32,
+            }
+        };
+        let u_id = ty_from_ident(&u.ident);
+        bvc.ingest_union(&u, &Namespace::new());
+        assert!(bvc.satisfy_requests(vec![u_id]).is_err());
+    }
 }
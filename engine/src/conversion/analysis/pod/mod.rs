@@ -18,12 +18,12 @@ use std::collections::HashSet;
 
 use autocxx_parser::TypeConfig;
 use byvalue_checker::ByValueChecker;
-use syn::{Item, ItemStruct};
+use syn::{Field, Item};
 
 use crate::{
     conversion::{
         api::{Api, ApiAnalysis, ApiDetail, TypeKind, UnanalyzedApi},
-        codegen_rs::make_non_pod,
+        codegen_rs::{make_non_pod, make_non_pod_union},
         parse::type_converter::TypeConverter,
         ConvertError,
     },
@@ -79,41 +79,66 @@ fn analyze_pod_api(
     let mut new_deps = api.deps;
     let api_detail = match api.detail {
         // No changes to any of these...
-        ApiDetail::ConcreteType { rs_definition } => ApiDetail::ConcreteType { rs_definition },
+        ApiDetail::ConcreteType {
+            rs_definition,
+            helpers,
+        } => ApiDetail::ConcreteType {
+            rs_definition,
+            helpers,
+        },
+        ApiDetail::PrimitiveWrapper { payload } => ApiDetail::PrimitiveWrapper { payload },
         ApiDetail::ForwardDeclaration => ApiDetail::ForwardDeclaration,
         ApiDetail::StringConstructor => ApiDetail::StringConstructor,
         ApiDetail::Function { fun, analysis } => ApiDetail::Function { fun, analysis },
         ApiDetail::Const { const_item } => ApiDetail::Const { const_item },
         ApiDetail::Typedef { payload } => ApiDetail::Typedef { payload },
         ApiDetail::CType { typename } => ApiDetail::CType { typename },
+        ApiDetail::ExternCppType { rust_path } => ApiDetail::ExternCppType { rust_path },
+        ApiDetail::BitfieldUnit { items } => ApiDetail::BitfieldUnit { items },
         // Just changes to this one...
         ApiDetail::Type {
             mut bindgen_mod_item,
+            mut bitfield_accessors,
             analysis: _,
         } => {
             let type_kind = if byvalue_checker.is_pod(&ty_id) {
                 // It's POD so let's mark dependencies on things in its field
-                if let Some(Item::Struct(ref s)) = bindgen_mod_item {
-                    get_struct_field_types(
+                match bindgen_mod_item {
+                    Some(Item::Struct(ref s)) => get_struct_field_types(
                         type_converter,
                         &api.name.get_namespace(),
-                        &s,
+                        s.fields.iter(),
                         &mut new_deps,
                         extra_apis,
-                    )?;
-                } // otherwise might be an enum, etc.
+                    )?,
+                    Some(Item::Union(ref u)) => get_struct_field_types(
+                        type_converter,
+                        &api.name.get_namespace(),
+                        u.fields.named.iter(),
+                        &mut new_deps,
+                        extra_apis,
+                    )?,
+                    _ => {} // otherwise might be an enum, etc.
+                }
                 TypeKind::Pod
             } else {
-                // It's non-POD. So also, make the fields opaque...
-                if let Some(Item::Struct(ref mut s)) = bindgen_mod_item {
-                    make_non_pod(s);
-                } // otherwise might be an enum, etc.
-                  // ... and say we don't depend on other types.
+                // It's non-POD. So also, make the fields opaque,
+                // along with any bitfields...
+                match bindgen_mod_item {
+                    Some(Item::Struct(ref mut s)) => make_non_pod(s),
+                    Some(Item::Union(u)) => {
+                        bindgen_mod_item = Some(Item::Struct(make_non_pod_union(u)))
+                    }
+                    _ => {} // otherwise might be an enum, etc.
+                }
+                bitfield_accessors.clear();
+                // ... and say we don't depend on other types.
                 new_deps.clear();
                 TypeKind::NonPod
             };
             ApiDetail::Type {
                 bindgen_mod_item,
+                bitfield_accessors,
                 analysis: type_kind,
             }
         }
@@ -127,14 +152,14 @@ fn analyze_pod_api(
     })
 }
 
-fn get_struct_field_types(
+fn get_struct_field_types<'a>(
     type_converter: &mut TypeConverter,
     ns: &Namespace,
-    s: &ItemStruct,
+    fields: impl Iterator<Item = &'a Field>,
     deps: &mut HashSet<QualifiedName>,
     extra_apis: &mut Vec<UnanalyzedApi>,
 ) -> Result<(), ConvertError> {
-    for f in &s.fields {
+    for f in fields {
         let annotated = type_converter.convert_type(f.ty.clone(), ns, false, &HashSet::new())?;
         extra_apis.extend(annotated.extra_apis);
         deps.extend(annotated.types_encountered);
@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::types::QualifiedName;
+use crate::{known_types::known_types, types::QualifiedName};
 use std::collections::HashSet;
-use syn::{ForeignItemFn, Ident, ImplItem, Item, ItemConst, ItemType, ItemUse, Type};
+use syn::{
+    parse_quote, ForeignItemFn, Ident, ImplItem, Item, ItemConst, ItemType, ItemUse, Type, TypePath,
+};
 
 use super::{convert_error::ErrorContext, parse::type_converter::TypeConverter, ConvertError};
 
@@ -39,6 +41,93 @@ pub(crate) struct ImplBlockDetails {
     pub(crate) item: ImplItem,
     pub(crate) ty: Ident,
 }
+
+/// Extra helper functions which we generate for some concrete types.
+#[derive(Clone)]
+pub(crate) enum ConcreteTypeHelpers {
+    Map(MapTypes),
+    Pair(PairTypes),
+    WideString(WideString),
+}
+
+/// A `std::u16string` or `std::wstring`, for which we generate functions
+/// to convert to and from Rust strings.
+#[derive(Clone)]
+pub(crate) enum WideString {
+    U16String,
+    WString,
+}
+
+impl WideString {
+    /// The name of a generated helper function for the string type
+    /// called `string_name`. Shared between C++ and Rust codegen.
+    pub(crate) fn helper_name(string_name: &str, helper: &str) -> String {
+        format!("{}_autocxx_{}", string_name, helper)
+    }
+}
+
+/// The key and value types of a `std::map` or `std::unordered_map`,
+/// for which we generate helper functions.
+#[derive(Clone)]
+pub(crate) struct MapTypes {
+    pub(crate) key: Box<Type>,
+    pub(crate) value: Box<Type>,
+    /// Whether this is a `std::map` as opposed to a `std::unordered_map`.
+    pub(crate) ordered: bool,
+    /// Whether the value type is POD, such that values can be copied
+    /// into a Rust map. This is decided during function analysis.
+    pub(crate) value_is_pod: bool,
+}
+
+impl MapTypes {
+    /// The name of a generated helper function (or type) for the map
+    /// type called `map_name`. Shared between C++ and Rust codegen.
+    pub(crate) fn helper_name(map_name: &str, helper: &str) -> String {
+        format!("{}_autocxx_map_{}", map_name, helper)
+    }
+}
+
+/// The element types of a `std::pair`, for which we generate a getter
+/// per element.
+#[derive(Clone)]
+pub(crate) struct PairTypes {
+    pub(crate) first: Box<Type>,
+    pub(crate) second: Box<Type>,
+    /// Whether both elements are POD, primitive or a string, such that
+    /// functions returning this type by value can instead return a Rust
+    /// tuple. This is decided during function analysis.
+    pub(crate) marshallable: bool,
+}
+
+impl PairTypes {
+    /// The name of a generated helper function (or type) for the pair
+    /// type called `pair_name`. Shared between C++ and Rust codegen.
+    pub(crate) fn helper_name(pair_name: &str, helper: &str) -> String {
+        format!("{}_autocxx_pair_{}", pair_name, helper)
+    }
+
+    /// Whether this element is a `std::string`, which we can only
+    /// marshall as a `UniquePtr<CxxString>`.
+    pub(crate) fn is_string(ty: &Type) -> bool {
+        match ty {
+            Type::Path(typ) => {
+                known_types().convertible_from_strs(&QualifiedName::from_type_path(typ))
+            }
+            _ => false,
+        }
+    }
+
+    /// The type of the field of the struct, shared with cxx, into which
+    /// we unpack this element of a marshallable pair.
+    pub(crate) fn field_type(ty: &Type) -> Type {
+        if Self::is_string(ty) {
+            parse_quote! { cxx::UniquePtr<cxx::CxxString> }
+        } else {
+            ty.clone()
+        }
+    }
+}
+
 /// A ForeignItemFn with a little bit of context about the
 /// type which is most likely to be 'this'
 #[derive(Clone)]
@@ -73,8 +162,16 @@ pub(crate) enum ApiDetail<T: ApiAnalysis> {
     /// A forward declared type for which no definition is available.
     ForwardDeclaration,
     /// A synthetic type we've manufactured in order to
-    /// concretize some templated C++ type.
-    ConcreteType { rs_definition: Box<Type> },
+    /// concretize some templated C++ type, or to represent a
+    /// `std::u16string` or `std::wstring`. For maps, pairs and
+    /// those strings, we also generate helper functions to access it.
+    ConcreteType {
+        rs_definition: Box<Type>,
+        helpers: Option<ConcreteTypeHelpers>,
+    },
+    /// A synthetic newtype we've manufactured around a primitive,
+    /// because cxx can't hold primitives in a `UniquePtr`.
+    PrimitiveWrapper { payload: Box<Type> },
     /// A simple note that we want to make a constructor for
     /// a `std::string` on the heap.
     StringConstructor,
@@ -89,11 +186,20 @@ pub(crate) enum ApiDetail<T: ApiAnalysis> {
     /// to pass on in our output
     Typedef { payload: TypedefKind },
     /// A type (struct or enum) encountered in the
-    /// `bindgen` output.
+    /// `bindgen` output. If it's a struct with bitfields, we also
+    /// keep the getters and setters which bindgen generated for them.
     Type {
         bindgen_mod_item: Option<Item>,
+        bitfield_accessors: Vec<ImplItem>,
         analysis: T::TypeAnalysis,
     },
+    /// The type which `bindgen` uses to store bitfields, along with
+    /// its impl blocks. This is purely Rust, so we pass it through
+    /// unchanged.
+    BitfieldUnit { items: Vec<Item> },
+    /// A type for which bindings already exist elsewhere, e.g. in
+    /// another crate, as specified by `extern_cpp_type!`.
+    ExternCppType { rust_path: TypePath },
     /// A variable-length C integer type (e.g. int, unsigned long).
     CType { typename: QualifiedName },
     /// A typedef which doesn't point to any actual useful kind of
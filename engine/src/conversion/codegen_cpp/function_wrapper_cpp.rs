@@ -13,32 +13,166 @@
 // limitations under the License.
 
 use crate::conversion::{
-    analysis::fun::function_wrapper::{CppConversionType, TypeConversionPolicy},
+    analysis::fun::function_wrapper::{CppConversionType, TypeConversionPolicy, UserDataPosition},
+    api::PairTypes,
     ConvertError,
 };
 use crate::known_types::type_lacks_copy_constructor;
+use syn::{Type, TypeReference, TypeSlice};
 
-use super::type_to_cpp;
+use super::{type_to_cpp, Header};
 
 impl TypeConversionPolicy {
     pub(super) fn unconverted_type(&self) -> Result<String, ConvertError> {
         match self.cpp_conversion {
-            CppConversionType::FromUniquePtrToValue => self.wrapped_type(),
+            CppConversionType::FromUniquePtrToValue
+            | CppConversionType::FromUniquePtrToOptional => self.wrapped_type(),
+            CppConversionType::FromPtrToOptional => {
+                Ok(format!("const {}*", self.unwrapped_type_as_string()?))
+            }
+            CppConversionType::FromStrToStringView => Ok("::rust::Str".to_string()),
+            CppConversionType::FromStringViewToBytes => Ok("std::string_view".to_string()),
+            CppConversionType::FromBoxToStdFunction(ref closure_type) => {
+                Ok(format!("::rust::Box<{}>", closure_type))
+            }
+            CppConversionType::FromRefToFnPtrWithUserData(ref closure_type, _) => {
+                Ok(format!("const {}&", closure_type))
+            }
+            CppConversionType::FromUsizeToFnPtr => Ok("size_t".to_string()),
             _ => self.unwrapped_type_as_string(),
         }
     }
 
     pub(super) fn converted_type(&self) -> Result<String, ConvertError> {
         match self.cpp_conversion {
-            CppConversionType::FromValueToUniquePtr => self.wrapped_type(),
+            CppConversionType::FromValueToUniquePtr
+            | CppConversionType::FromOptionalToUniquePtr => self.wrapped_type(),
+            CppConversionType::FromValueToSharedPtr => Ok(format!(
+                "std::shared_ptr<{}>",
+                self.unwrapped_type_as_string()?
+            )),
+            CppConversionType::FromOptionalToOutPtr => Ok("bool".to_string()),
+            CppConversionType::FromStrToStringView => Ok("std::string_view".to_string()),
+            CppConversionType::FromStringViewToBytes => {
+                Ok("::rust::Slice<const uint8_t>".to_string())
+            }
+            CppConversionType::FromPairToStruct(..) => Ok(self.pair_struct_name()),
             _ => self.unwrapped_type_as_string(),
         }
     }
 
+    /// Any extra parameter needed by the wrapper function to pass
+    /// out its result.
+    pub(super) fn out_param(&self) -> Result<Option<String>, ConvertError> {
+        Ok(match self.cpp_conversion {
+            CppConversionType::FromOptionalToOutPtr => {
+                Some(format!("{}* autocxx_out", self.unwrapped_type_as_string()?))
+            }
+            _ => None,
+        })
+    }
+
+    /// Headers needed by the code generated for this conversion.
+    pub(super) fn headers(&self) -> Vec<Header> {
+        match self.cpp_conversion {
+            CppConversionType::FromPtrToOptional
+            | CppConversionType::FromUniquePtrToOptional
+            | CppConversionType::FromOptionalToUniquePtr => vec![Header::system("optional")],
+            CppConversionType::FromOptionalToOutPtr => {
+                vec![Header::system("optional"), Header::system("new")]
+            }
+            CppConversionType::FromStrToStringView | CppConversionType::FromStringViewToBytes => {
+                vec![
+                    Header::system("cstdint"),
+                    Header::system("string_view"),
+                    Header::user("cxx.h"),
+                ]
+            }
+            CppConversionType::FromStdArrayToPtr => vec![Header::system("array")],
+            CppConversionType::FromSliceToPtrAndLen => vec![Header::user("cxx.h")],
+            CppConversionType::FromSliceToSpan => {
+                vec![Header::system("span"), Header::user("cxx.h")]
+            }
+            CppConversionType::FromBoxToStdFunction(_) => vec![
+                Header::system("functional"),
+                Header::system("utility"),
+                Header::user("cxx.h"),
+            ],
+            CppConversionType::FromRefToFnPtrWithUserData(..) => vec![Header::user("cxx.h")],
+            CppConversionType::FromUsizeToFnPtr => vec![Header::system("cstddef")],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Headers needed only by the definition of the wrapper function,
+    /// typically because they'd otherwise be circular.
+    pub(super) fn definition_headers(&self) -> Vec<Header> {
+        match self.cpp_conversion {
+            // Calling the closure requires the full definition of the Rust
+            // type, which cxx generates.
+            CppConversionType::FromBoxToStdFunction(_)
+            | CppConversionType::FromRefToFnPtrWithUserData(..) => vec![Header::user("cxxgen.h")],
+            // Likewise, returning the struct requires its full definition.
+            CppConversionType::FromPairToStruct(..) => vec![Header::user("cxxgen.h")],
+            _ => Vec::new(),
+        }
+    }
+
     fn unwrapped_type_as_string(&self) -> Result<String, ConvertError> {
         type_to_cpp(&self.unwrapped_type)
     }
 
+    /// The element type of a slice, `const`-qualified unless the slice
+    /// is mutable.
+    fn slice_element_type_as_string(&self) -> Result<String, ConvertError> {
+        if let Type::Reference(TypeReference {
+            elem, mutability, ..
+        }) = &self.unwrapped_type
+        {
+            if let Type::Slice(TypeSlice { elem, .. }) = elem.as_ref() {
+                let constness = if mutability.is_some() { "" } else { "const " };
+                return Ok(format!("{}{}", constness, type_to_cpp(elem)?));
+            }
+        }
+        panic!("Slice conversion applied to a type other than a slice")
+    }
+
+    /// A C++ lambda which forwards its arguments to the closure, and the
+    /// list of parameters and the return type of that lambda.
+    fn closure_forwarding_lambda_parts(
+        &self,
+    ) -> Result<(Vec<String>, Vec<String>, String), ConvertError> {
+        let (arg_types, ret_type) = self.callback_signature();
+        let params = arg_types
+            .iter()
+            .enumerate()
+            .map(|(counter, ty)| Ok(format!("{} autocxx_arg{}", type_to_cpp(ty)?, counter)))
+            .collect::<Result<Vec<_>, ConvertError>>()?;
+        let args = (0..arg_types.len())
+            .map(|counter| format!("autocxx_arg{}", counter))
+            .collect();
+        let ret_type = match ret_type {
+            None => "void".to_string(),
+            Some(ty) => type_to_cpp(&ty)?,
+        };
+        Ok((params, args, ret_type))
+    }
+
+    /// The C++ spelling of a pointer to a function with the signature
+    /// of this callback.
+    fn fn_ptr_type_as_string(&self) -> Result<String, ConvertError> {
+        let (arg_types, ret_type) = self.callback_signature();
+        let arg_types = arg_types
+            .iter()
+            .map(type_to_cpp)
+            .collect::<Result<Vec<_>, ConvertError>>()?;
+        let ret_type = match ret_type {
+            None => "void".to_string(),
+            Some(ty) => type_to_cpp(&ty)?,
+        };
+        Ok(format!("{}(*)({})", ret_type, arg_types.join(", ")))
+    }
+
     fn wrapped_type(&self) -> Result<String, ConvertError> {
         Ok(format!(
             "std::unique_ptr<{}>",
@@ -61,6 +195,114 @@ impl TypeConversionPolicy {
                 self.unconverted_type()?,
                 var_name
             ),
+            CppConversionType::FromValueToSharedPtr => format!(
+                "std::make_shared<{}>({})",
+                self.unconverted_type()?,
+                var_name
+            ),
+            CppConversionType::FromPtrToOptional => format!(
+                "({} ? std::optional<{}>(*{}) : std::nullopt)",
+                var_name,
+                self.unwrapped_type_as_string()?,
+                var_name
+            ),
+            CppConversionType::FromUniquePtrToOptional => format!(
+                "({} ? std::optional<{}>(std::move(*{})) : std::nullopt)",
+                var_name,
+                self.unwrapped_type_as_string()?,
+                var_name
+            ),
+            CppConversionType::FromStrToStringView => {
+                format!("std::string_view({}.data(), {}.size())", var_name, var_name)
+            }
+            CppConversionType::FromStdArrayToPtr => format!("{}.data()", var_name),
+            // This expands to two arguments for the call to the underlying function.
+            CppConversionType::FromSliceToPtrAndLen => {
+                format!("{}.data(), {}.size()", var_name, var_name)
+            }
+            CppConversionType::FromSliceToSpan => format!(
+                "std::span<{}>({}.data(), {}.size())",
+                self.slice_element_type_as_string()?,
+                var_name,
+                var_name
+            ),
+            // The std::function may be copied, so share ownership of
+            // the closure between all the copies.
+            CppConversionType::FromBoxToStdFunction(_) => {
+                let (params, args, ret_type) = self.closure_forwarding_lambda_parts()?;
+                format!(
+                    "[autocxx_closure = std::make_shared<{}>(std::move({}))]({}) -> {} {{ return (*autocxx_closure)->call({}); }}",
+                    self.unconverted_type()?,
+                    var_name,
+                    params.join(", "),
+                    ret_type,
+                    args.join(", ")
+                )
+            }
+            // This expands to two arguments for the call to the underlying
+            // function: the function pointer and its user data. The closure
+            // is owned on the Rust side, by a CallbackHandle.
+            CppConversionType::FromRefToFnPtrWithUserData(ref closure_type, ref user_data) => {
+                let (mut params, args, ret_type) = self.closure_forwarding_lambda_parts()?;
+                let user_data_param = format!(
+                    "{}void* autocxx_user_data",
+                    if user_data.is_const { "const " } else { "" }
+                );
+                match user_data.position {
+                    UserDataPosition::First => params.insert(0, user_data_param),
+                    UserDataPosition::Last => params.push(user_data_param),
+                }
+                format!(
+                    "[]({}) -> {} {{ return static_cast<const {}*>(autocxx_user_data)->call({}); }}, const_cast<void*>(static_cast<const void*>(&{}))",
+                    params.join(", "),
+                    ret_type,
+                    closure_type,
+                    args.join(", "),
+                    var_name
+                )
+            }
+            CppConversionType::FromUsizeToFnPtr => format!(
+                "reinterpret_cast<{}>({})",
+                self.fn_ptr_type_as_string()?,
+                var_name
+            ),
+            CppConversionType::FromOptionalToOutPtr
+            | CppConversionType::FromOptionalToUniquePtr
+            | CppConversionType::FromStringViewToBytes
+            | CppConversionType::FromPairToStruct(..) => {
+                panic!("Conversion only applicable to return values")
+            }
+        })
+    }
+
+    /// Statements to return the result of `call` from the wrapper function,
+    /// converting it as necessary.
+    pub(super) fn cpp_return(&self, call: &str) -> Result<String, ConvertError> {
+        Ok(match self.cpp_conversion {
+            CppConversionType::FromOptionalToOutPtr => format!(
+                "auto autocxx_result = {}; if (!autocxx_result) {{ return false; }} new (autocxx_out) {}(std::move(*autocxx_result)); return true",
+                call,
+                self.unwrapped_type_as_string()?
+            ),
+            CppConversionType::FromOptionalToUniquePtr => format!(
+                "auto autocxx_result = {}; if (!autocxx_result) {{ return nullptr; }} return std::make_unique<{}>(std::move(*autocxx_result))",
+                call,
+                self.unwrapped_type_as_string()?
+            ),
+            // An empty view may have a null data pointer, which isn't allowed
+            // in a Rust slice, so use the default slice in that case.
+            CppConversionType::FromStringViewToBytes => format!(
+                "auto autocxx_result = {}; if (autocxx_result.empty()) {{ return {}(); }} return {}(reinterpret_cast<const uint8_t*>(autocxx_result.data()), autocxx_result.size())",
+                call,
+                self.converted_type()?,
+                self.converted_type()?
+            ),
+            CppConversionType::FromPairToStruct(..) => format!(
+                "return {}({})",
+                PairTypes::helper_name(&self.unwrapped_type_as_string()?, "to_struct"),
+                call
+            ),
+            _ => format!("return {}", self.cpp_conversion(call)?),
         })
     }
 }
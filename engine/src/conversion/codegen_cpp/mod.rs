@@ -26,7 +26,7 @@ use super::{
         function_wrapper::{FunctionWrapper, FunctionWrapperPayload},
         FnAnalysis,
     },
-    api::Api,
+    api::{Api, ConcreteTypeHelpers, MapTypes, PairTypes, WideString},
     ConvertError,
 };
 
@@ -36,7 +36,7 @@ pub(crate) enum AdditionalNeed {
     MakeStringConstructor,
     FunctionWrapper(Box<FunctionWrapper>),
     CTypeTypedef(QualifiedName),
-    ConcreteTemplatedTypeTypedef(QualifiedName, Box<Type>),
+    ConcreteTemplatedTypeTypedef(QualifiedName, Box<Type>, Option<ConcreteTypeHelpers>),
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Hash)]
@@ -71,6 +71,7 @@ struct AdditionalFunction {
     declaration: String,
     definition: String,
     headers: Vec<Header>,
+    definition_headers: Vec<Header>,
 }
 
 /// Details of additional generated C++.
@@ -120,8 +121,20 @@ impl CppCodeGenerator {
                     self.generate_by_value_wrapper(&by_value_wrapper)?
                 }
                 AdditionalNeed::CTypeTypedef(tn) => self.generate_ctype_typedef(&tn),
-                AdditionalNeed::ConcreteTemplatedTypeTypedef(tn, def) => {
-                    self.generate_typedef(&tn, type_to_cpp(&def)?)
+                AdditionalNeed::ConcreteTemplatedTypeTypedef(tn, def, helpers) => {
+                    self.generate_typedef(&tn, type_to_cpp(&def)?);
+                    match helpers {
+                        Some(ConcreteTypeHelpers::Map(map_types)) => {
+                            self.generate_map_helpers(&tn, &map_types)?
+                        }
+                        Some(ConcreteTypeHelpers::Pair(pair_types)) => {
+                            self.generate_pair_helpers(&tn, &pair_types)?
+                        }
+                        Some(ConcreteTypeHelpers::WideString(kind)) => {
+                            self.generate_wide_string_helpers(&tn, &kind)
+                        }
+                        None => {}
+                    }
                 }
             }
         }
@@ -145,8 +158,20 @@ impl CppCodeGenerator {
                 "{}\n{}\n{}\n{}",
                 headers, self.inclusions, type_definitions, declarations
             );
+            let definition_headers: HashSet<Header> = self
+                .additional_functions
+                .iter()
+                .flat_map(|x| x.definition_headers.iter().cloned())
+                .collect();
+            let definition_headers = definition_headers
+                .iter()
+                .map(|x| x.include_stmt())
+                .join("\n");
             let definitions = self.concat_additional_items(|x| &x.definition);
-            let definitions = format!("#include \"autocxxgen.h\"\n{}", definitions);
+            let definitions = format!(
+                "#include \"autocxxgen.h\"\n{}\n{}",
+                definition_headers, definitions
+            );
             Some(CppCodegenResults {
                 declarations,
                 definitions,
@@ -184,6 +209,7 @@ impl CppCodeGenerator {
                 Header::system("string"),
                 Header::user("cxx.h"),
             ],
+            definition_headers: Vec::new(),
         })
     }
 
@@ -223,7 +249,15 @@ impl CppCodeGenerator {
                 ))
             })
             .collect();
-        let args = args?.join(", ");
+        let mut args = args?;
+        if let Some(out_param) = details
+            .return_conversion
+            .as_ref()
+            .map_or(Ok(None), |x| x.out_param())?
+        {
+            args.push(out_param);
+        }
+        let args = args.join(", ");
         let ret_type = details
             .return_conversion
             .as_ref()
@@ -261,20 +295,287 @@ impl CppCodeGenerator {
             }
         };
         if let Some(ret) = &details.return_conversion {
-            underlying_function_call =
-                format!("return {}", ret.cpp_conversion(&underlying_function_call)?);
+            underlying_function_call = ret.cpp_return(&underlying_function_call)?;
         };
         let definition = format!("{} {{ {}; }}", declaration, underlying_function_call,);
         let declaration = format!("{};", declaration);
+        let headers = std::iter::once(Header::system("memory"))
+            .chain(
+                details
+                    .argument_conversion
+                    .iter()
+                    .chain(details.return_conversion.iter())
+                    .flat_map(|conv| conv.headers()),
+            )
+            .collect();
+        let definition_headers = details
+            .argument_conversion
+            .iter()
+            .flat_map(|conv| conv.definition_headers())
+            .collect();
+        // Rust types wrapping closures are defined by cxx, but we need to
+        // refer to them in our declarations.
+        let type_definition = details
+            .argument_conversion
+            .iter()
+            .filter_map(|conv| conv.closure_type())
+            .map(|closure_type| format!("struct {};", closure_type))
+            .join("\n");
         self.additional_functions.push(AdditionalFunction {
-            type_definition: "".into(),
+            type_definition,
+            declaration,
+            definition,
+            headers,
+            definition_headers,
+        });
+        Ok(())
+    }
+
+    /// Generates the functions with which Rust can access a
+    /// `std::map` or `std::unordered_map`, including a simple way
+    /// to walk through it with an iterator.
+    fn generate_map_helpers(
+        &mut self,
+        tn: &QualifiedName,
+        map_types: &MapTypes,
+    ) -> Result<(), ConvertError> {
+        let map = tn.get_final_item();
+        let key = type_to_cpp(&map_types.key)?;
+        let value = type_to_cpp(&map_types.value)?;
+        let name = |helper| MapTypes::helper_name(map, helper);
+        let iter = name("iterator");
+        let functions = [
+            (
+                format!("size_t {}(const {}& map)", name("len"), map),
+                "return map.size();".to_string(),
+            ),
+            (
+                format!(
+                    "bool {}(const {}& map, const {}& key)",
+                    name("contains"),
+                    map,
+                    key
+                ),
+                "return map.count(key) != 0;".to_string(),
+            ),
+            (
+                format!(
+                    "const {}* {}(const {}& map, const {}& key)",
+                    value,
+                    name("get"),
+                    map,
+                    key
+                ),
+                "auto it = map.find(key); return it == map.end() ? nullptr : &it->second;"
+                    .to_string(),
+            ),
+            (
+                format!(
+                    "void {}({}& map, const {}& key, const {}& value)",
+                    name("insert"),
+                    map,
+                    key,
+                    value
+                ),
+                "auto result = map.insert({key, value}); if (!result.second) { result.first->second = value; }".to_string(),
+            ),
+            (
+                format!("std::unique_ptr<{}> {}()", map, name("new")),
+                format!("return std::make_unique<{}>();", map),
+            ),
+            (
+                format!("std::unique_ptr<{}> {}(const {}& map)", iter, name("begin"), map),
+                format!("return std::make_unique<{}>(map.begin());", iter),
+            ),
+            (
+                format!(
+                    "bool {}(const {}& map, const {}& iter)",
+                    name("iter_done"),
+                    map,
+                    iter
+                ),
+                "return iter == map.end();".to_string(),
+            ),
+            (
+                format!(
+                    "const {}& {}(const {}&, const {}& iter)",
+                    key,
+                    name("iter_key"),
+                    map,
+                    iter
+                ),
+                "return iter->first;".to_string(),
+            ),
+            (
+                format!(
+                    "const {}& {}(const {}&, const {}& iter)",
+                    value,
+                    name("iter_value"),
+                    map,
+                    iter
+                ),
+                "return iter->second;".to_string(),
+            ),
+            (
+                format!("void {}({}& iter)", name("iter_next"), iter),
+                "++iter;".to_string(),
+            ),
+        ];
+        let declaration = functions
+            .iter()
+            .map(|(signature, _)| format!("{};", signature))
+            .join("\n");
+        let definition = functions
+            .iter()
+            .map(|(signature, body)| format!("{} {{ {} }}", signature, body))
+            .join("\n");
+        let map_header = if map_types.ordered {
+            "map"
+        } else {
+            "unordered_map"
+        };
+        self.additional_functions.push(AdditionalFunction {
+            type_definition: format!("typedef {}::const_iterator {};", map, iter),
             declaration,
             definition,
-            headers: vec![Header::system("memory")],
+            headers: vec![
+                Header::system("cstddef"),
+                Header::system("memory"),
+                Header::system(map_header),
+            ],
+            definition_headers: Vec::new(),
         });
         Ok(())
     }
 
+    /// Generates a getter for each element of a `std::pair`. If we can
+    /// marshall it into a Rust tuple, also generates a function to unpack
+    /// it into the struct which cxx shares with Rust.
+    fn generate_pair_helpers(
+        &mut self,
+        tn: &QualifiedName,
+        pair_types: &PairTypes,
+    ) -> Result<(), ConvertError> {
+        let pair = tn.get_final_item();
+        let name = |helper: &str| PairTypes::helper_name(pair, helper);
+        let mut functions = Vec::new();
+        for (field, ty) in [("first", &pair_types.first), ("second", &pair_types.second)].iter() {
+            functions.push((
+                format!(
+                    "const {}& {}(const {}& pair)",
+                    type_to_cpp(ty)?,
+                    name(field),
+                    pair
+                ),
+                format!("return pair.{};", field),
+            ));
+        }
+        let mut type_definition = String::new();
+        let mut definition_headers = Vec::new();
+        if pair_types.marshallable {
+            let struct_name = name("struct");
+            let field = |field: &str, ty: &Type| {
+                let element = format!("std::move(pair.{})", field);
+                if PairTypes::is_string(ty) {
+                    format!("std::make_unique<std::string>({})", element)
+                } else {
+                    element
+                }
+            };
+            functions.push((
+                format!("{} {}({} pair)", struct_name, name("to_struct"), pair),
+                format!(
+                    "return {}{{ {}, {} }};",
+                    struct_name,
+                    field("first", &pair_types.first),
+                    field("second", &pair_types.second)
+                ),
+            ));
+            // cxx defines the struct, so we can only forward declare it.
+            type_definition = format!("struct {};", struct_name);
+            definition_headers.push(Header::user("cxxgen.h"));
+        }
+        let declaration = functions
+            .iter()
+            .map(|(signature, _)| format!("{};", signature))
+            .join("\n");
+        let definition = functions
+            .iter()
+            .map(|(signature, body)| format!("{} {{ {} }}", signature, body))
+            .join("\n");
+        self.additional_functions.push(AdditionalFunction {
+            type_definition,
+            declaration,
+            definition,
+            headers: vec![
+                Header::system("memory"),
+                Header::system("string"),
+                Header::system("utility"),
+            ],
+            definition_headers,
+        });
+        Ok(())
+    }
+
+    /// Generates the functions with which Rust can copy the code units
+    /// out of a `std::u16string` or `std::wstring`, and create a new one
+    /// from code units.
+    fn generate_wide_string_helpers(&mut self, tn: &QualifiedName, kind: &WideString) {
+        let string = tn.get_final_item();
+        let name = |helper: &str| WideString::helper_name(string, helper);
+        let (unit, char_type) = match kind {
+            WideString::U16String => ("uint16_t", "char16_t"),
+            WideString::WString => ("uint32_t", "wchar_t"),
+        };
+        let functions = [
+            (
+                format!(
+                    "std::unique_ptr<std::vector<{}>> {}(const {}& s)",
+                    unit,
+                    name("to_units"),
+                    string
+                ),
+                format!(
+                    "return std::make_unique<std::vector<{}>>(s.begin(), s.end());",
+                    unit
+                ),
+            ),
+            (
+                format!(
+                    "std::unique_ptr<{}> {}(::rust::Slice<const {}> units)",
+                    string,
+                    name("from_units"),
+                    unit
+                ),
+                format!(
+                    "auto s = std::make_unique<{}>(); s->reserve(units.size()); for (auto unit : units) {{ s->push_back(static_cast<{}>(unit)); }} return s;",
+                    string, char_type
+                ),
+            ),
+        ];
+        let declaration = functions
+            .iter()
+            .map(|(signature, _)| format!("{};", signature))
+            .join("\n");
+        let definition = functions
+            .iter()
+            .map(|(signature, body)| format!("{} {{ {} }}", signature, body))
+            .join("\n");
+        self.additional_functions.push(AdditionalFunction {
+            type_definition: "".into(),
+            declaration,
+            definition,
+            headers: vec![
+                Header::system("cstdint"),
+                Header::system("memory"),
+                Header::system("string"),
+                Header::system("vector"),
+                Header::user("cxx.h"),
+            ],
+            definition_headers: Vec::new(),
+        })
+    }
+
     fn generate_ctype_typedef(&mut self, tn: &QualifiedName) {
         let cpp_name = tn.to_cpp_name();
         self.generate_typedef(tn, cpp_name)
@@ -287,6 +588,7 @@ impl CppCodeGenerator {
             declaration: "".into(),
             definition: "".into(),
             headers: Vec::new(),
+            definition_headers: Vec::new(),
         })
     }
 }
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{conversion::ConvertError, types::QualifiedName};
+use crate::{
+    conversion::ConvertError,
+    types::{array_len, QualifiedName},
+};
 use quote::ToTokens;
 use syn::{Token, Type};
 
@@ -53,18 +56,36 @@ pub(crate) fn type_to_cpp(ty: &Type) -> Result<String, ConvertError> {
                 Some(suffix) => Ok(format!("{}<{}>", root, suffix)),
             }
         }
-        Type::Reference(typr) => Ok(format!(
-            "{}{}&",
-            get_mut_string(&typr.mutability),
-            type_to_cpp(typr.elem.as_ref())?
-        )),
+        // cxx represents slices as rust::Slices.
+        Type::Reference(typr) => match typr.elem.as_ref() {
+            Type::Slice(typs) => Ok(format!(
+                "::rust::Slice<{}{}>",
+                get_mut_string(&typr.mutability),
+                type_to_cpp(typs.elem.as_ref())?
+            )),
+            _ => Ok(format!(
+                "{}{}&",
+                get_mut_string(&typr.mutability),
+                type_to_cpp(typr.elem.as_ref())?
+            )),
+        },
         Type::Ptr(typp) => Ok(format!(
             "{}{}*",
             get_mut_string(&typp.mutability),
             type_to_cpp(typp.elem.as_ref())?
         )),
-        Type::Array(_)
-        | Type::BareFn(_)
+        // cxx represents arrays as std::arrays.
+        Type::Array(typa) => match array_len(typa) {
+            Some(len) => Ok(format!(
+                "std::array<{}, {}>",
+                type_to_cpp(typa.elem.as_ref())?,
+                len
+            )),
+            None => Err(ConvertError::UnsupportedType(
+                ty.to_token_stream().to_string(),
+            )),
+        },
+        Type::BareFn(_)
         | Type::Group(_)
         | Type::ImplTrait(_)
         | Type::Infer(_)
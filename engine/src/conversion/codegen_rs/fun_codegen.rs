@@ -27,7 +27,10 @@ use super::{
 use crate::{conversion::api::FuncToConvert, types::make_ident};
 use crate::{
     conversion::{
-        analysis::fun::{ArgumentAnalysis, FnAnalysisBody, FnKind, MethodKind, RustRenameStrategy},
+        analysis::fun::{
+            function_wrapper::TypeConversionPolicy, ArgumentAnalysis, FnAnalysisBody, FnKind,
+            MethodKind, RustRenameStrategy,
+        },
         api::ImplBlockDetails,
     },
     types::{Namespace, QualifiedName},
@@ -41,6 +44,7 @@ pub(super) fn gen_function(
     let cxxbridge_name = analysis.cxxbridge_name;
     let rust_name = analysis.rust_name;
     let ret_type = analysis.ret_type;
+    let ret_conversion = analysis.ret_conversion;
     let param_details = analysis.param_details;
     let cpp_call_name = analysis.cpp_call_name;
     let wrapper_function_needed = analysis.cpp_wrapper.is_some();
@@ -56,6 +60,19 @@ pub(super) fn gen_function(
     } else {
         None
     };
+    // Some conversions pass raw pointers to the cxx::bridge function,
+    // in which case that must be unsafe even if the wrapper is not.
+    let bridge_unsafety: Option<Unsafe> = if analysis.requires_unsafe
+        || param_details
+            .iter()
+            .map(|pd| &pd.conversion)
+            .chain(ret_conversion.iter())
+            .any(|conversion| conversion.bridge_requires_unsafe())
+    {
+        Some(parse_quote!(unsafe))
+    } else {
+        None
+    };
     let rust_name_attr: Vec<_> = match &analysis.rust_rename_strategy {
         RustRenameStrategy::RenameUsingRustAttr => Attribute::parse_outer
             .parse2(quote!(
@@ -74,9 +91,29 @@ pub(super) fn gen_function(
     let any_param_needs_rust_conversion = param_details
         .iter()
         .any(|pd| pd.conversion.rust_work_needed());
+    let ret_needs_rust_conversion = ret_conversion
+        .as_ref()
+        .map_or(false, |conversion| conversion.rust_work_needed());
     let rust_wrapper_needed = any_param_needs_rust_conversion
+        || ret_needs_rust_conversion
         || (cxxbridge_name != rust_name && matches!(kind, FnKind::Method(..)));
     if rust_wrapper_needed {
+        let returns_callback_handle = param_details
+            .iter()
+            .any(|pd| pd.conversion.needs_callback_handle());
+        // Nothing stops C++ calling a callback after its handle has been
+        // dropped, so only the caller can promise that it won't.
+        let unsafety = if returns_callback_handle {
+            Some(parse_quote!(unsafe))
+        } else {
+            unsafety
+        };
+        let wrapper_ret = WrapperReturn {
+            ret_type: &ret_type,
+            ret_conversion: ret_conversion.as_ref(),
+            needs_unsafe_block: bridge_unsafety.is_some() && unsafety.is_none(),
+            returns_callback_handle,
+        };
         if let FnKind::Method(ref type_name, ref method_kind) = kind {
             // Method, or static method.
             impl_entry = Some(generate_method_impl(
@@ -85,7 +122,7 @@ pub(super) fn gen_function(
                 type_name,
                 &cxxbridge_name,
                 &rust_name,
-                &ret_type,
+                &wrapper_ret,
                 &unsafety,
                 &doc_attr,
             ));
@@ -94,12 +131,22 @@ pub(super) fn gen_function(
             materialization = Use::Custom(generate_function_impl(
                 &param_details,
                 &rust_name,
-                &ret_type,
+                &wrapper_ret,
                 &unsafety,
                 &doc_attr,
             ));
         }
     }
+    // Any closures passed as callbacks need a Rust type which C++ can call.
+    let (closure_global_items, closure_bridge_items): (Vec<_>, Vec<_>) =
+        if analysis.closures_generated_elsewhere {
+            (Vec::new(), Vec::new())
+        } else {
+            param_details
+                .iter()
+                .filter_map(|pd| pd.conversion.closure_items())
+                .unzip()
+        };
     if cxxbridge_name != cpp_call_name && !wrapper_function_needed {
         cpp_name_attr = Attribute::parse_outer
             .parse2(quote!(
@@ -135,18 +182,117 @@ pub(super) fn gen_function(
         #(#rust_name_attr)*
         #(#cpp_name_attr)*
         #doc_attr
-        #vis #unsafety fn #cxxbridge_name ( #params ) #ret_type;
+        #vis #bridge_unsafety fn #cxxbridge_name ( #params ) #ret_type;
     ));
     RsCodegenResult {
         extern_c_mod_item: Some(extern_c_mod_item),
-        bridge_items: Vec::new(),
-        global_items: Vec::new(),
-        bindgen_mod_item: None,
+        bridge_items: closure_bridge_items,
+        global_items: closure_global_items.into_iter().flatten().collect(),
+        bindgen_mod_items: Vec::new(),
         impl_entry,
         materialization,
     }
 }
 
+/// How a Rust wrapper function returns the results of the
+/// cxx::bridge function which it calls.
+struct WrapperReturn<'a> {
+    ret_type: &'a ReturnType,
+    ret_conversion: Option<&'a TypeConversionPolicy>,
+    needs_unsafe_block: bool,
+    /// Whether closures passed as function pointers are kept alive by a
+    /// `CallbackHandle`. That's returned as the second element of a tuple,
+    /// after whatever the function itself returns, rather than through an
+    /// out parameter, so that the caller can't forget it exists. Such
+    /// wrappers are `unsafe`, since it's up to the caller to keep the
+    /// handle until C++ has finished with the callbacks.
+    returns_callback_handle: bool,
+}
+
+impl<'a> WrapperReturn<'a> {
+    /// The type returned by the wrapper function, other than any
+    /// `CallbackHandle`.
+    fn converted_ret_type(&self) -> ReturnType {
+        match self.ret_conversion {
+            Some(conversion) if conversion.rust_work_needed() => {
+                let ty = conversion.rust_wrapper_return_type();
+                parse_quote! { -> #ty }
+            }
+            _ => self.ret_type.clone(),
+        }
+    }
+
+    /// Documents the safety contract of a wrapper which returns a
+    /// `CallbackHandle`.
+    fn safety_doc(&self) -> Vec<Attribute> {
+        if !self.returns_callback_handle {
+            return Vec::new();
+        }
+        Attribute::parse_outer
+            .parse2(quote! {
+                #[doc = ""]
+                #[doc = " # Safety"]
+                #[doc = ""]
+                #[doc = " The returned `CallbackHandle` owns the closures passed as callbacks."]
+                #[doc = " It must not be dropped while C++ might still call any of them."]
+            })
+            .unwrap()
+    }
+
+    fn wrapper_ret_type(&self) -> ReturnType {
+        let ret_type = self.converted_ret_type();
+        if !self.returns_callback_handle {
+            return ret_type;
+        }
+        match ret_type {
+            ReturnType::Default => parse_quote! { -> autocxx::CallbackHandle },
+            ReturnType::Type(_, ty) => parse_quote! { -> (#ty, autocxx::CallbackHandle) },
+        }
+    }
+
+    /// Generates the body of the wrapper function given the list
+    /// of arguments to pass to the cxx::bridge function.
+    fn wrapper_body(&self, cxxbridge_name: &Ident, mut arg_list: Vec<TokenStream>) -> TokenStream {
+        let body = match self.ret_conversion {
+            Some(conversion) if conversion.rust_work_needed() => {
+                if conversion.out_param_rust_type().is_some() {
+                    arg_list.push(quote! { autocxx_out.as_mut_ptr() });
+                }
+                conversion.rust_return_conversion(quote! {
+                    cxxbridge::#cxxbridge_name ( #(#arg_list),* )
+                })
+            }
+            _ => quote! {
+                cxxbridge::#cxxbridge_name ( #(#arg_list),* )
+            },
+        };
+        let body = if self.needs_unsafe_block {
+            quote! {
+                unsafe { #body }
+            }
+        } else {
+            body
+        };
+        if !self.returns_callback_handle {
+            return body;
+        }
+        let result = match self.converted_ret_type() {
+            ReturnType::Default => quote! {
+                { #body };
+                autocxx_callbacks
+            },
+            ReturnType::Type(..) => quote! {
+                let autocxx_ret = { #body };
+                (autocxx_ret, autocxx_callbacks)
+            },
+        };
+        quote! {
+            let mut autocxx_callbacks = autocxx::CallbackHandle::default();
+            #result
+        }
+    }
+}
+
 fn generate_arg_lists(
     param_details: &[ArgumentAnalysis],
     is_constructor: bool,
@@ -177,17 +323,21 @@ fn generate_method_impl(
     impl_block_type_name: &QualifiedName,
     cxxbridge_name: &Ident,
     rust_name: &str,
-    ret_type: &ReturnType,
+    wrapper_ret: &WrapperReturn,
     unsafety: &Option<Unsafe>,
     doc_attr: &Option<Attribute>,
 ) -> Box<ImplBlockDetails> {
     let (wrapper_params, arg_list) = generate_arg_lists(param_details, is_constructor);
     let rust_name = make_ident(&rust_name);
+    let ret_type = wrapper_ret.wrapper_ret_type();
+    let body = wrapper_ret.wrapper_body(cxxbridge_name, arg_list);
+    let safety_doc = wrapper_ret.safety_doc();
     Box::new(ImplBlockDetails {
         item: ImplItem::Method(parse_quote! {
             #doc_attr
+            #(#safety_doc)*
             pub #unsafety fn #rust_name ( #wrapper_params ) #ret_type {
-                cxxbridge::#cxxbridge_name ( #(#arg_list),* )
+                #body
             }
         }),
         ty: impl_block_type_name.get_final_ident(),
@@ -198,16 +348,20 @@ fn generate_method_impl(
 fn generate_function_impl(
     param_details: &[ArgumentAnalysis],
     rust_name: &str,
-    ret_type: &ReturnType,
+    wrapper_ret: &WrapperReturn,
     unsafety: &Option<Unsafe>,
     doc_attr: &Option<Attribute>,
 ) -> Box<Item> {
     let (wrapper_params, arg_list) = generate_arg_lists(param_details, false);
     let rust_name = make_ident(&rust_name);
+    let ret_type = wrapper_ret.wrapper_ret_type();
+    let body = wrapper_ret.wrapper_body(&rust_name, arg_list);
+    let safety_doc = wrapper_ret.safety_doc();
     Box::new(Item::Fn(parse_quote! {
         #doc_attr
+        #(#safety_doc)*
         pub #unsafety fn #rust_name ( #wrapper_params ) #ret_type {
-            cxxbridge::#rust_name ( #(#arg_list),* )
+            #body
         }
     }))
 }
@@ -13,10 +13,12 @@
 // limitations under the License.
 
 use proc_macro2::TokenStream;
-use syn::{Pat, Type};
+use syn::{Item, Pat, Type};
 
-use crate::conversion::analysis::fun::function_wrapper::{
-    RustConversionType, TypeConversionPolicy,
+use super::unqualify::unqualify_type;
+use crate::{
+    conversion::analysis::fun::function_wrapper::{RustConversionType, TypeConversionPolicy},
+    types::make_ident,
 };
 use quote::quote;
 use syn::parse_quote;
@@ -24,15 +26,149 @@ use syn::parse_quote;
 impl TypeConversionPolicy {
     pub(super) fn rust_wrapper_unconverted_type(&self) -> Type {
         match self.rust_conversion {
-            RustConversionType::None => self.converted_rust_type(),
             RustConversionType::FromStr => parse_quote! { impl ToCppString },
+            RustConversionType::FromOptionToPtr => self.make_option_type(),
+            RustConversionType::FromOptionToUniquePtr => self.make_option_of_unique_ptr_type(),
+            // C++ may call the closure from any thread, and concurrently
+            // if it copies a std::function.
+            RustConversionType::FromClosure | RustConversionType::FromClosureKeptInHandle => {
+                let (arg_types, ret_type) = self.callback_signature();
+                let ret_type = ret_type.map(|ty| quote! { -> #ty });
+                parse_quote! { impl Fn( #(#arg_types),* ) #ret_type + Send + Sync + 'static }
+            }
+            RustConversionType::FromExternFn => {
+                let (arg_types, ret_type) = self.callback_signature();
+                let ret_type = ret_type.map(|ty| quote! { -> #ty });
+                parse_quote! { extern "C" fn( #(#arg_types),* ) #ret_type }
+            }
+            _ => self.converted_rust_type(),
         }
     }
 
     pub(super) fn rust_conversion(&self, var: Pat) -> TokenStream {
         match self.rust_conversion {
-            RustConversionType::None => quote! { #var },
             RustConversionType::FromStr => quote! ( #var .into_cpp() ),
+            RustConversionType::FromOptionToPtr => {
+                quote! ( #var .as_ref().map_or(std::ptr::null(), |v| v as *const _) )
+            }
+            RustConversionType::FromOptionToUniquePtr => {
+                quote! ( #var .unwrap_or_else(cxx::UniquePtr::null) )
+            }
+            RustConversionType::FromClosure => quote! ( #var .into() ),
+            // The wrapper function declares the handle, and returns it.
+            RustConversionType::FromClosureKeptInHandle => {
+                quote! ( &*autocxx_callbacks.register( #var .into() ) )
+            }
+            RustConversionType::FromExternFn => quote! ( #var as usize ),
+            _ => quote! { #var },
+        }
+    }
+
+    /// The type returned by the Rust wrapper function.
+    pub(super) fn rust_wrapper_return_type(&self) -> Type {
+        match self.rust_conversion {
+            RustConversionType::ToOptionFromOutPtr => self.make_option_type(),
+            RustConversionType::ToOptionFromUniquePtr => self.make_option_of_unique_ptr_type(),
+            RustConversionType::ToStrFromBytes => {
+                let ty = &self.unwrapped_type;
+                parse_quote! { Result<#ty, std::str::Utf8Error> }
+            }
+            RustConversionType::ToTupleFromStruct => {
+                let (first, second) = self.pair_field_types();
+                parse_quote! { ( #first, #second ) }
+            }
+            _ => self.unconverted_rust_type(),
+        }
+    }
+
+    /// Converts the result of `call`, a call to the cxx::bridge function,
+    /// into the type returned by the Rust wrapper function. If this
+    /// conversion needs an out parameter, `call` should pass
+    /// `autocxx_out.as_mut_ptr()`.
+    pub(super) fn rust_return_conversion(&self, call: TokenStream) -> TokenStream {
+        match self.rust_conversion {
+            RustConversionType::ToOptionFromOutPtr => quote! {
+                let mut autocxx_out = std::mem::MaybeUninit::uninit();
+                if #call {
+                    Some(autocxx_out.assume_init())
+                } else {
+                    None
+                }
+            },
+            RustConversionType::ToOptionFromUniquePtr => quote! {
+                let autocxx_result = #call;
+                if autocxx_result.is_null() {
+                    None
+                } else {
+                    Some(autocxx_result)
+                }
+            },
+            RustConversionType::ToStrFromBytes => quote! {
+                std::str::from_utf8(#call)
+            },
+            RustConversionType::ToTupleFromStruct => quote! {
+                let autocxx_result = #call;
+                ( autocxx_result.first, autocxx_result.second )
+            },
+            _ => call,
+        }
+    }
+
+    /// If this conversion creates a callback from a Rust closure,
+    /// generates the Rust type which wraps the closure. Returns the items
+    /// which go alongside the cxx::bridge mod, and the item which goes
+    /// inside it.
+    pub(super) fn closure_items(&self) -> Option<(Vec<Item>, Item)> {
+        let closure_type = self.closure_type()?;
+        let (arg_types, ret_type) = self.callback_signature();
+        let arg_names: Vec<_> = (0..arg_types.len())
+            .map(|counter| make_ident(format!("arg{}", counter)))
+            .collect();
+        let bridge_arg_types = arg_types.iter().cloned().map(unqualify_type);
+        let bridge_ret_type = ret_type
+            .clone()
+            .map(unqualify_type)
+            .map(|ty| quote! { -> #ty });
+        let ret_type = ret_type.map(|ty| quote! { -> #ty });
+        let global_items = vec![
+            Item::Struct(parse_quote! {
+                pub struct #closure_type(Box<dyn Fn( #(#arg_types),* ) #ret_type + Send + Sync>);
+            }),
+            Item::Impl(parse_quote! {
+                impl<F: Fn( #(#arg_types),* ) #ret_type + Send + Sync + 'static> From<F> for Box<#closure_type> {
+                    fn from(f: F) -> Self {
+                        Box::new(#closure_type(Box::new(f)))
+                    }
+                }
+            }),
+            Item::Impl(parse_quote! {
+                impl #closure_type {
+                    fn call(&self, #(#arg_names: #arg_types),* ) #ret_type {
+                        (self.0)( #(#arg_names),* )
+                    }
+                }
+            }),
+        ];
+        let bridge_item = Item::ForeignMod(parse_quote! {
+            extern "Rust" {
+                type #closure_type;
+                fn call(self: &#closure_type, #(#arg_names: #bridge_arg_types),* ) #bridge_ret_type;
+            }
+        });
+        Some((global_items, bridge_item))
+    }
+
+    fn make_option_type(&self) -> Type {
+        let innerty = &self.unwrapped_type;
+        parse_quote! {
+            Option < #innerty >
+        }
+    }
+
+    fn make_option_of_unique_ptr_type(&self) -> Type {
+        let innerty = &self.unwrapped_type;
+        parse_quote! {
+            Option < cxx::UniquePtr < #innerty > >
         }
     }
 }
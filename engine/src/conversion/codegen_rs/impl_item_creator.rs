@@ -12,15 +12,56 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use syn::{parse_quote, Ident, Item};
+use std::collections::HashSet;
 
-pub(crate) fn create_impl_items(id: &Ident) -> Vec<Item> {
-    vec![
-        Item::Impl(parse_quote! {
-            impl UniquePtr<#id> {}
-        }),
-        Item::Impl(parse_quote! {
-            impl CxxVector<#id> {}
-        }),
-    ]
+use syn::{parse_quote, GenericArgument, Ident, Item, PathArguments, Type};
+
+/// `SharedPtr` and `WeakPtr` impls are only generated for types which
+/// are held in one somewhere, as found by [find_shared_ptr_payloads].
+pub(crate) fn create_impl_items(id: &Ident, shared_ptr_payloads: &HashSet<Ident>) -> Vec<Item> {
+    let mut items = vec![Item::Impl(parse_quote! {
+        impl UniquePtr<#id> {}
+    })];
+    if shared_ptr_payloads.contains(id) {
+        items.push(Item::Impl(parse_quote! {
+            impl SharedPtr<#id> {}
+        }));
+        items.push(Item::Impl(parse_quote! {
+            impl WeakPtr<#id> {}
+        }));
+    }
+    items.push(Item::Impl(parse_quote! {
+        impl CxxVector<#id> {}
+    }));
+    items
+}
+
+/// Records the final identifier of any type held in a `SharedPtr` or a
+/// `WeakPtr` anywhere within this type.
+pub(crate) fn find_shared_ptr_payloads(ty: &Type, payloads: &mut HashSet<Ident>) {
+    match ty {
+        Type::Path(typ) => {
+            for seg in &typ.path.segments {
+                if let PathArguments::AngleBracketed(ab) = &seg.arguments {
+                    for arg in &ab.args {
+                        if let GenericArgument::Type(inner) = arg {
+                            if seg.ident == "SharedPtr" || seg.ident == "WeakPtr" {
+                                if let Type::Path(inner_typ) = inner {
+                                    if let Some(last_seg) = inner_typ.path.segments.last() {
+                                        payloads.insert(last_seg.ident.clone());
+                                    }
+                                }
+                            }
+                            find_shared_ptr_payloads(inner, payloads);
+                        }
+                    }
+                }
+            }
+        }
+        Type::Reference(tr) => find_shared_ptr_payloads(&tr.elem, payloads),
+        Type::Ptr(tp) => find_shared_ptr_payloads(&tp.elem, payloads),
+        Type::Array(ta) => find_shared_ptr_payloads(&ta.elem, payloads),
+        Type::Slice(ts) => find_shared_ptr_payloads(&ts.elem, payloads),
+        _ => {}
+    }
 }
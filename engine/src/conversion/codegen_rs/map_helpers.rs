@@ -0,0 +1,271 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Item, Type};
+
+use crate::{
+    conversion::api::MapTypes,
+    known_types::known_types,
+    types::{make_ident, QualifiedName},
+};
+
+use super::unqualify::unqualify_type;
+
+/// How a map key or value can be copied into an owned Rust type,
+/// for conversions to and from Rust maps.
+enum OwnedConversion {
+    /// A primitive, which is just cloned.
+    Primitive,
+    /// A `CxxString`, which becomes a `String`.
+    String,
+    /// A POD struct, which is byte-copyable but needn't implement `Clone`,
+    /// `Ord` or `Hash`, so can only be a value.
+    Pod,
+}
+
+impl OwnedConversion {
+    fn new(ty: &Type, is_key: bool, is_pod: bool) -> Option<Self> {
+        let tn = match ty {
+            Type::Path(typ) => QualifiedName::from_type_path(typ),
+            _ => return None,
+        };
+        if known_types().convertible_from_strs(&tn) {
+            Some(Self::String)
+        } else if known_types().is_primitive(&tn) {
+            // Floats are neither Ord nor Hash so can't be keys in Rust maps.
+            let cpp_name = tn.to_cpp_name();
+            if is_key && (cpp_name == "float" || cpp_name == "double") {
+                None
+            } else {
+                Some(Self::Primitive)
+            }
+        } else if is_pod && !is_key {
+            Some(Self::Pod)
+        } else {
+            None
+        }
+    }
+
+    fn owned_type(&self, ty: &Type) -> Type {
+        match self {
+            Self::Primitive | Self::Pod => ty.clone(),
+            Self::String => parse_quote! { String },
+        }
+    }
+
+    fn to_owned(&self, var: &TokenStream) -> TokenStream {
+        match self {
+            Self::Primitive => quote! { #var.clone() },
+            Self::String => quote! { #var.to_string_lossy().into_owned() },
+            // POD types are, by definition, safe to copy bytewise.
+            Self::Pod => quote! { unsafe { ::std::ptr::read(#var) } },
+        }
+    }
+
+    /// Shadows `var` (a reference to the owned type) with a reference
+    /// to the C++ type.
+    fn borrow_as_cpp(&self, var: &TokenStream) -> TokenStream {
+        match self {
+            Self::Primitive | Self::Pod => TokenStream::new(),
+            Self::String => quote! {
+                cxx::let_cxx_string!(#var = #var);
+                let #var: &cxx::CxxString = &#var;
+            },
+        }
+    }
+}
+
+/// Generates Rust accessors for a `std::map` or `std::unordered_map`,
+/// calling the C++ helper functions generated alongside its typedef.
+/// Returns global items and items for the cxx::bridge.
+pub(super) fn generate_map_helpers(
+    name: &QualifiedName,
+    map_types: &MapTypes,
+) -> (Vec<Item>, Vec<Item>) {
+    let id = name.get_final_ident();
+    let fulltypath = name.get_bindgen_path_idents();
+    let map = quote! { #(#fulltypath)::* };
+    let map_name = id.to_string();
+    let helper = |helper| make_ident(MapTypes::helper_name(&map_name, helper));
+    let (len, contains, get, insert, new) = (
+        helper("len"),
+        helper("contains"),
+        helper("get"),
+        helper("insert"),
+        helper("new"),
+    );
+    let (iter_type, begin, iter_done, iter_key, iter_value, iter_next) = (
+        helper("iterator"),
+        helper("begin"),
+        helper("iter_done"),
+        helper("iter_key"),
+        helper("iter_value"),
+        helper("iter_next"),
+    );
+    let iter_struct = make_ident(format!("{}Iter", map_name));
+    let key = map_types.key.as_ref();
+    let value = map_types.value.as_ref();
+    let bridge_key = unqualify_type(key.clone());
+    let bridge_value = unqualify_type(value.clone());
+
+    let bridge_items = vec![Item::Verbatim(quote! {
+        unsafe extern "C++" {
+            type #iter_type;
+            fn #len(map: &#id) -> usize;
+            fn #contains(map: &#id, key: &#bridge_key) -> bool;
+            fn #get(map: &#id, key: &#bridge_key) -> *const #bridge_value;
+            fn #insert(map: Pin<&mut #id>, key: &#bridge_key, value: &#bridge_value);
+            fn #new() -> UniquePtr<#id>;
+            fn #begin(map: &#id) -> UniquePtr<#iter_type>;
+            fn #iter_done(map: &#id, iter: &#iter_type) -> bool;
+            fn #iter_key<'a>(map: &'a #id, iter: &#iter_type) -> &'a #bridge_key;
+            fn #iter_value<'a>(map: &'a #id, iter: &#iter_type) -> &'a #bridge_value;
+            fn #iter_next(iter: Pin<&mut #iter_type>);
+        }
+    })];
+
+    let mut global_items = vec![
+        Item::Impl(parse_quote! {
+            impl #map {
+                /// Creates a new, empty map.
+                pub fn make_unique() -> cxx::UniquePtr<Self> {
+                    cxxbridge::#new()
+                }
+
+                /// Returns the number of entries in the map.
+                pub fn len(&self) -> usize {
+                    cxxbridge::#len(self)
+                }
+
+                /// Returns `true` if the map has no entries.
+                pub fn is_empty(&self) -> bool {
+                    self.len() == 0
+                }
+
+                /// Returns `true` if the map has an entry for `key`.
+                pub fn contains_key(&self, key: &#key) -> bool {
+                    cxxbridge::#contains(self, key)
+                }
+
+                /// Returns the value for `key`, if there is one.
+                pub fn get(&self, key: &#key) -> Option<&#value> {
+                    // The pointer is null if there's no such key, and
+                    // otherwise points into the map, which we borrow.
+                    unsafe { cxxbridge::#get(self, key).as_ref() }
+                }
+
+                /// Copies `key` and `value` into the map, replacing any
+                /// existing value for `key`.
+                pub fn insert(self: ::std::pin::Pin<&mut Self>, key: &#key, value: &#value) {
+                    cxxbridge::#insert(self, key, value)
+                }
+
+                /// Iterates over the entries in the map.
+                pub fn iter(&self) -> #iter_struct<'_> {
+                    #iter_struct {
+                        map: self,
+                        iter: cxxbridge::#begin(self),
+                    }
+                }
+            }
+        }),
+        Item::Struct(parse_quote! {
+            /// Iterator over the entries in a C++ map.
+            pub struct #iter_struct<'a> {
+                map: &'a #map,
+                iter: cxx::UniquePtr<cxxbridge::#iter_type>,
+            }
+        }),
+        Item::Impl(parse_quote! {
+            impl<'a> Iterator for #iter_struct<'a> {
+                type Item = (&'a #key, &'a #value);
+
+                fn next(&mut self) -> Option<Self::Item> {
+                    if cxxbridge::#iter_done(self.map, &self.iter) {
+                        return None;
+                    }
+                    let entry = (
+                        cxxbridge::#iter_key(self.map, &self.iter),
+                        cxxbridge::#iter_value(self.map, &self.iter),
+                    );
+                    cxxbridge::#iter_next(self.iter.pin_mut());
+                    Some(entry)
+                }
+            }
+        }),
+        Item::Impl(parse_quote! {
+            impl<'a> IntoIterator for &'a #map {
+                type Item = (&'a #key, &'a #value);
+                type IntoIter = #iter_struct<'a>;
+
+                fn into_iter(self) -> Self::IntoIter {
+                    self.iter()
+                }
+            }
+        }),
+    ];
+
+    // Conversions to and from Rust maps, if we know how to copy
+    // the keys and values.
+    if let (Some(key_conversion), Some(value_conversion)) = (
+        OwnedConversion::new(key, true, false),
+        OwnedConversion::new(value, false, map_types.value_is_pod),
+    ) {
+        let (rust_map, to_rust_map, from_rust_map) = if map_types.ordered {
+            (
+                quote! { ::std::collections::BTreeMap },
+                make_ident("to_btree_map"),
+                make_ident("from_btree_map"),
+            )
+        } else {
+            (
+                quote! { ::std::collections::HashMap },
+                make_ident("to_hash_map"),
+                make_ident("from_hash_map"),
+            )
+        };
+        let owned_key = key_conversion.owned_type(key);
+        let owned_value = value_conversion.owned_type(value);
+        let (key_var, value_var) = (quote! { key }, quote! { value });
+        let key_to_owned = key_conversion.to_owned(&key_var);
+        let value_to_owned = value_conversion.to_owned(&value_var);
+        let borrow_key = key_conversion.borrow_as_cpp(&key_var);
+        let borrow_value = value_conversion.borrow_as_cpp(&value_var);
+        global_items.push(Item::Impl(parse_quote! {
+            impl #map {
+                /// Copies the entries in this map into a Rust map.
+                pub fn #to_rust_map(&self) -> #rust_map<#owned_key, #owned_value> {
+                    self.iter()
+                        .map(|(key, value)| (#key_to_owned, #value_to_owned))
+                        .collect()
+                }
+
+                /// Creates a new map containing copies of the entries in
+                /// a Rust map.
+                pub fn #from_rust_map(map: &#rust_map<#owned_key, #owned_value>) -> cxx::UniquePtr<Self> {
+                    let mut result = Self::make_unique();
+                    for (key, value) in map {
+                        #borrow_key
+                        #borrow_value
+                        result.pin_mut().insert(key, value);
+                    }
+                    result
+                }
+            }
+        }));
+    }
+    (global_items, bridge_items)
+}
@@ -16,21 +16,28 @@ mod doc_attr;
 mod fun_codegen;
 mod function_wrapper_rs;
 mod impl_item_creator;
+mod map_helpers;
 mod namespace_organizer;
 mod non_pod_struct;
+mod pair_helpers;
 mod unqualify;
+mod wide_string_helpers;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 // The following should not need to be exposed outside
 // codegen_rs but currently Rust codegen happens everywhere... TODO
-pub(crate) use non_pod_struct::make_non_pod;
+pub(crate) use non_pod_struct::{make_non_pod, make_non_pod_union};
 
-use proc_macro2::TokenStream;
-use syn::{parse_quote, ForeignItem, Ident, Item, ItemForeignMod, ItemMod};
+use autocxx_parser::{CppInclusion, TypeConfig};
+use proc_macro2::{TokenStream, TokenTree};
+use syn::{parse_quote, ForeignItem, Ident, Item, ItemForeignMod, ItemMod, ReturnType, Type};
 
 use crate::types::{make_ident, Namespace, QualifiedName};
-use impl_item_creator::create_impl_items;
+use impl_item_creator::{create_impl_items, find_shared_ptr_payloads};
+use map_helpers::generate_map_helpers;
+use pair_helpers::generate_pair_helpers;
+use wide_string_helpers::generate_wide_string_helpers;
 
 use self::{
     fun_codegen::gen_function,
@@ -40,7 +47,9 @@ use self::{
 
 use super::{
     analysis::fun::FnAnalysis,
-    api::{Api, ApiAnalysis, ApiDetail, ImplBlockDetails, TypeKind, TypedefKind},
+    api::{
+        Api, ApiAnalysis, ApiDetail, ConcreteTypeHelpers, ImplBlockDetails, TypeKind, TypedefKind,
+    },
 };
 use super::{convert_error::ErrorContext, ConvertError};
 use quote::quote;
@@ -109,24 +118,54 @@ fn remove_nones<T>(input: Vec<Option<T>>) -> Vec<T> {
     input.into_iter().flatten().collect()
 }
 
+/// cxx spells a system include as tokens, which it glues back together
+/// to get the path. Paths from a config file needn't survive that, in
+/// which case this returns `None`.
+fn system_include_tokens(path: &str) -> Option<TokenStream> {
+    let tokens: TokenStream = path.parse().ok()?;
+    let mut reassembled = String::new();
+    for token in tokens.clone() {
+        match token {
+            TokenTree::Ident(ident) => reassembled.push_str(&ident.to_string()),
+            TokenTree::Literal(lit)
+                if lit.to_string().starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                reassembled.push_str(&lit.to_string())
+            }
+            TokenTree::Punct(punct) => reassembled.push(punct.as_char()),
+            _ => return None,
+        }
+    }
+    if reassembled == path {
+        Some(tokens)
+    } else {
+        None
+    }
+}
+
 /// Type which handles generation of Rust code.
 /// In practice, much of the "generation" involves connecting together
 /// existing lumps of code within the Api structures.
 pub(crate) struct RsCodeGenerator<'a> {
-    include_list: &'a [String],
+    include_list: &'a [CppInclusion],
     bindgen_mod: ItemMod,
+    type_config: &'a TypeConfig,
+    shared_ptr_payloads: HashSet<Ident>,
 }
 
 impl<'a> RsCodeGenerator<'a> {
     /// Generate code for a set of APIs that was discovered during parsing.
     pub(crate) fn generate_rs_code(
         all_apis: Vec<Api<FnAnalysis>>,
-        include_list: &'a [String],
+        include_list: &'a [CppInclusion],
         bindgen_mod: ItemMod,
+        type_config: &'a TypeConfig,
     ) -> Vec<Item> {
         let c = Self {
             include_list,
             bindgen_mod,
+            type_config,
+            shared_ptr_payloads: HashSet::new(),
         };
         c.rs_codegen(all_apis)
     }
@@ -138,12 +177,13 @@ impl<'a> RsCodeGenerator<'a> {
         let generate_utilities = all_apis
             .iter()
             .any(|api| matches!(&api.detail, ApiDetail::StringConstructor));
+        self.shared_ptr_payloads = Self::find_all_shared_ptr_payloads(&all_apis);
         // Now let's generate the Rust code.
         let (rs_codegen_results_and_namespaces, additional_cpp_needs): (Vec<_>, Vec<_>) = all_apis
             .into_iter()
             .map(|api| {
                 let more_cpp_needed = api.additional_cpp().is_some();
-                let gen = Self::generate_rs_for_api(&api.name, api.detail);
+                let gen = self.generate_rs_for_api(&api.name, api.detail);
                 ((api.name, gen), more_cpp_needed)
             })
             .unzip();
@@ -207,6 +247,43 @@ impl<'a> RsCodeGenerator<'a> {
         all_items
     }
 
+    /// Types held in a `SharedPtr` or `WeakPtr` anywhere in these APIs,
+    /// including those returned by a generated `make_shared`.
+    fn find_all_shared_ptr_payloads(all_apis: &[Api<FnAnalysis>]) -> HashSet<Ident> {
+        let mut payloads = HashSet::new();
+        for api in all_apis {
+            match &api.detail {
+                ApiDetail::Function { analysis, .. } => {
+                    let conversions = analysis
+                        .param_details
+                        .iter()
+                        .map(|pd| &pd.conversion)
+                        .chain(analysis.ret_conversion.iter());
+                    for conversion in conversions {
+                        find_shared_ptr_payloads(&conversion.converted_rust_type(), &mut payloads);
+                        find_shared_ptr_payloads(
+                            &conversion.unconverted_rust_type(),
+                            &mut payloads,
+                        );
+                    }
+                    if let ReturnType::Type(_, ty) = &analysis.ret_type {
+                        find_shared_ptr_payloads(ty, &mut payloads);
+                    }
+                }
+                ApiDetail::Type {
+                    bindgen_mod_item: Some(Item::Struct(s)),
+                    ..
+                } => {
+                    for field in &s.fields {
+                        find_shared_ptr_payloads(&field.ty, &mut payloads);
+                    }
+                }
+                _ => {}
+            }
+        }
+        payloads
+    }
+
     fn make_foreign_mod_unsafe(ifm: ItemForeignMod) -> Item {
         // At the moment syn does not support outputting 'unsafe extern "C"' except in verbatim
         // items. See https://github.com/dtolnay/syn/pull/938
@@ -217,16 +294,25 @@ impl<'a> RsCodeGenerator<'a> {
 
     fn build_include_foreign_items(&self, has_additional_cpp_needs: bool) -> Vec<ForeignItem> {
         let extra_inclusion = if has_additional_cpp_needs {
-            Some("autocxxgen.h".to_string())
+            Some(CppInclusion::Quoted("autocxxgen.h".to_string()))
         } else {
             None
         };
         let chained = self.include_list.iter().chain(extra_inclusion.iter());
         chained
-            .map(|inc| {
-                ForeignItem::Macro(parse_quote! {
-                    include!(#inc);
-                })
+            .map(|inc| match inc {
+                CppInclusion::Quoted(path) => ForeignItem::Macro(parse_quote! {
+                    include!(#path);
+                }),
+                CppInclusion::System(path) => match system_include_tokens(path) {
+                    Some(tokens) => ForeignItem::Macro(parse_quote! {
+                        include!(<#tokens>);
+                    }),
+                    // A quoted include still searches the system include path.
+                    None => ForeignItem::Macro(parse_quote! {
+                        include!(#path);
+                    }),
+                },
             })
             .collect()
     }
@@ -317,7 +403,7 @@ impl<'a> RsCodeGenerator<'a> {
     ) {
         let mut impl_entries_by_type: HashMap<_, Vec<_>> = HashMap::new();
         for item in ns_entries.entries() {
-            output_items.extend(item.1.bindgen_mod_item.iter().cloned());
+            output_items.extend(item.1.bindgen_mod_items.iter().cloned());
             if let Some(impl_entry) = &item.1.impl_entry {
                 impl_entries_by_type
                     .entry(impl_entry.ty.clone())
@@ -374,6 +460,7 @@ impl<'a> RsCodeGenerator<'a> {
     }
 
     fn generate_rs_for_api(
+        &self,
         name: &QualifiedName,
         api_detail: ApiDetail<FnAnalysis>,
     ) -> RsCodegenResult {
@@ -385,25 +472,59 @@ impl<'a> RsCodeGenerator<'a> {
                 ))),
                 bridge_items: Vec::new(),
                 global_items: get_string_items(),
-                bindgen_mod_item: None,
+                bindgen_mod_items: Vec::new(),
                 impl_entry: None,
                 materialization: Use::Unused,
             },
-            ApiDetail::ConcreteType { .. } => RsCodegenResult {
-                global_items: Self::generate_extern_type_impl(TypeKind::NonPod, &name),
-                bridge_items: create_impl_items(&id),
+            ApiDetail::ConcreteType { helpers, .. } => {
+                let mut global_items = Self::generate_extern_type_impl(TypeKind::NonPod, &name);
+                let mut bridge_items = create_impl_items(&id, &self.shared_ptr_payloads);
+                let helper_items = match helpers {
+                    Some(ConcreteTypeHelpers::Map(map_types)) => {
+                        Some(generate_map_helpers(name, &map_types))
+                    }
+                    Some(ConcreteTypeHelpers::Pair(pair_types)) => {
+                        Some(generate_pair_helpers(name, &pair_types))
+                    }
+                    Some(ConcreteTypeHelpers::WideString(kind)) => {
+                        Some(generate_wide_string_helpers(name, &kind))
+                    }
+                    None => None,
+                };
+                if let Some((mut helper_global_items, mut helper_bridge_items)) = helper_items {
+                    global_items.append(&mut helper_global_items);
+                    bridge_items.append(&mut helper_bridge_items);
+                }
+                RsCodegenResult {
+                    global_items,
+                    bridge_items,
+                    extern_c_mod_item: Some(ForeignItem::Verbatim(Self::generate_cxxbridge_type(
+                        name,
+                    ))),
+                    bindgen_mod_items: vec![Item::Struct(new_non_pod_struct(id.clone()))],
+                    impl_entry: None,
+                    // Every concrete type is exposed, not just those with
+                    // helpers, since their names no longer depend on the
+                    // order in which they're found, and users need to name
+                    // them, e.g. as `UniquePtr<ffi::FooMap>`.
+                    materialization: Use::UsedFromCxxBridge,
+                }
+            }
+            ApiDetail::PrimitiveWrapper { payload } => RsCodegenResult {
+                global_items: Self::generate_extern_type_impl(TypeKind::Pod, name),
+                bridge_items: create_impl_items(&id, &self.shared_ptr_payloads),
                 extern_c_mod_item: Some(ForeignItem::Verbatim(Self::generate_cxxbridge_type(name))),
-                bindgen_mod_item: Some(Item::Struct(new_non_pod_struct(id.clone()))),
+                bindgen_mod_items: Self::generate_primitive_wrapper(&id, &payload),
                 impl_entry: None,
-                materialization: Use::Unused,
+                materialization: Use::UsedFromCxxBridge,
             },
             ApiDetail::ForwardDeclaration => RsCodegenResult {
                 extern_c_mod_item: Some(ForeignItem::Verbatim(Self::generate_cxxbridge_type(name))),
                 bridge_items: Vec::new(),
                 global_items: Self::generate_extern_type_impl(TypeKind::NonPod, &name),
-                bindgen_mod_item: Some(Item::Struct(new_non_pod_struct(id.clone()))),
+                bindgen_mod_items: vec![Item::Struct(new_non_pod_struct(id.clone()))],
                 impl_entry: None,
-                materialization: Use::UsedFromCxxBridge,
+                materialization: self.type_materialization(name),
             },
             ApiDetail::Function { fun, analysis } => {
                 gen_function(name.get_namespace(), fun, analysis)
@@ -413,34 +534,67 @@ impl<'a> RsCodeGenerator<'a> {
                 impl_entry: None,
                 bridge_items: Vec::new(),
                 extern_c_mod_item: None,
-                bindgen_mod_item: None,
+                bindgen_mod_items: Vec::new(),
                 materialization: Use::Unused,
             },
             ApiDetail::Typedef { payload } => RsCodegenResult {
                 extern_c_mod_item: None,
                 bridge_items: Vec::new(),
                 global_items: Vec::new(),
-                bindgen_mod_item: Some(match payload {
+                bindgen_mod_items: vec![match payload {
                     TypedefKind::Type(type_item) => Item::Type(type_item),
                     TypedefKind::Use(use_item) => Item::Use(use_item),
-                }),
+                }],
                 impl_entry: None,
                 materialization: Use::UsedFromBindgen,
             },
             ApiDetail::Type {
                 bindgen_mod_item,
+                bitfield_accessors,
                 analysis,
-            } => RsCodegenResult {
-                global_items: Self::generate_extern_type_impl(analysis, &name),
+            } => {
+                let mut bindgen_mod_items: Vec<_> = bindgen_mod_item.into_iter().collect();
+                if !bitfield_accessors.is_empty() {
+                    bindgen_mod_items.push(Item::Impl(parse_quote! {
+                        impl #id {
+                            #(#bitfield_accessors)*
+                        }
+                    }));
+                }
+                RsCodegenResult {
+                    global_items: Self::generate_extern_type_impl(analysis, &name),
+                    impl_entry: None,
+                    bridge_items: if analysis.can_be_instantiated() {
+                        create_impl_items(&id, &self.shared_ptr_payloads)
+                    } else {
+                        Vec::new()
+                    },
+                    extern_c_mod_item: Some(ForeignItem::Verbatim(Self::generate_cxxbridge_type(
+                        name,
+                    ))),
+                    bindgen_mod_items,
+                    materialization: self.type_materialization(name),
+                }
+            }
+            ApiDetail::BitfieldUnit { items } => RsCodegenResult {
+                global_items: Vec::new(),
                 impl_entry: None,
-                bridge_items: if analysis.can_be_instantiated() {
-                    create_impl_items(&id)
-                } else {
-                    Vec::new()
-                },
+                bridge_items: Vec::new(),
+                extern_c_mod_item: None,
+                bindgen_mod_items: items,
+                materialization: Use::Unused,
+            },
+            ApiDetail::ExternCppType { rust_path } => RsCodegenResult {
+                // The cxx::ExternType impl, and any UniquePtr etc. impls,
+                // already exist alongside the existing bindings.
+                global_items: Vec::new(),
+                impl_entry: None,
+                bridge_items: Vec::new(),
                 extern_c_mod_item: Some(ForeignItem::Verbatim(Self::generate_cxxbridge_type(name))),
-                bindgen_mod_item,
-                materialization: Use::UsedFromCxxBridge,
+                bindgen_mod_items: vec![Item::Use(parse_quote! {
+                    pub use #rust_path as #id;
+                })],
+                materialization: self.type_materialization(name),
             },
             ApiDetail::CType { .. } => RsCodegenResult {
                 global_items: Vec::new(),
@@ -449,23 +603,32 @@ impl<'a> RsCodeGenerator<'a> {
                 extern_c_mod_item: Some(ForeignItem::Verbatim(quote! {
                     type #id = autocxx::#id;
                 })),
-                bindgen_mod_item: None,
+                bindgen_mod_items: Vec::new(),
                 materialization: Use::Unused,
             },
             ApiDetail::OpaqueTypedef => RsCodegenResult {
                 global_items: Vec::new(),
                 impl_entry: None,
-                bridge_items: create_impl_items(&id),
+                bridge_items: create_impl_items(&id, &self.shared_ptr_payloads),
                 extern_c_mod_item: Some(ForeignItem::Type(parse_quote! {
                     type #id;
                 })),
-                bindgen_mod_item: None,
+                bindgen_mod_items: Vec::new(),
                 materialization: Use::Unused,
             },
             ApiDetail::IgnoredItem { err, ctx } => Self::generate_error_entry(err, ctx),
         }
     }
 
+    /// Types are exposed under their C++ name unless the user asked
+    /// for a different one using `rename!`.
+    fn type_materialization(&self, name: &QualifiedName) -> Use {
+        match self.type_config.get_rust_name(&name.to_cpp_name()) {
+            Some(rust_name) => Use::UsedFromCxxBridgeWithAlias(make_ident(rust_name)),
+            None => Use::UsedFromCxxBridge,
+        }
+    }
+
     /// Generates something in the output mod that will carry a docstring
     /// explaining why a given type or function couldn't have bindings
     /// generated.
@@ -496,7 +659,7 @@ impl<'a> RsCodeGenerator<'a> {
             impl_entry,
             bridge_items: Vec::new(),
             extern_c_mod_item: None,
-            bindgen_mod_item: None,
+            bindgen_mod_items: Vec::new(),
             materialization,
         }
     }
@@ -539,6 +702,55 @@ impl<'a> RsCodeGenerator<'a> {
         })]
     }
 
+    /// The newtype which lets cxx hold a primitive in a `UniquePtr`. It
+    /// derefs to, converts from and compares with the primitive itself, so
+    /// users rarely need to name it.
+    fn generate_primitive_wrapper(id: &Ident, payload: &Type) -> Vec<Item> {
+        vec![
+            Item::Struct(parse_quote! {
+                #[derive(Debug, Clone, PartialEq)]
+                #[repr(transparent)]
+                pub struct #id(pub #payload);
+            }),
+            Item::Impl(parse_quote! {
+                impl std::ops::Deref for #id {
+                    type Target = #payload;
+                    fn deref(&self) -> &#payload {
+                        &self.0
+                    }
+                }
+            }),
+            Item::Impl(parse_quote! {
+                impl std::ops::DerefMut for #id {
+                    fn deref_mut(&mut self) -> &mut #payload {
+                        &mut self.0
+                    }
+                }
+            }),
+            Item::Impl(parse_quote! {
+                impl From<#payload> for #id {
+                    fn from(val: #payload) -> Self {
+                        Self(val)
+                    }
+                }
+            }),
+            Item::Impl(parse_quote! {
+                impl PartialEq<#payload> for #id {
+                    fn eq(&self, other: &#payload) -> bool {
+                        self.0 == *other
+                    }
+                }
+            }),
+            Item::Impl(parse_quote! {
+                impl PartialEq<#payload> for &#id {
+                    fn eq(&self, other: &#payload) -> bool {
+                        self.0 == *other
+                    }
+                }
+            }),
+        ]
+    }
+
     fn generate_cxxbridge_type(name: &QualifiedName) -> TokenStream {
         let id = name.get_final_ident();
         let ns = name.get_namespace();
@@ -588,7 +800,20 @@ struct RsCodegenResult {
     extern_c_mod_item: Option<ForeignItem>,
     bridge_items: Vec<Item>,
     global_items: Vec<Item>,
-    bindgen_mod_item: Option<Item>,
+    bindgen_mod_items: Vec<Item>,
     impl_entry: Option<Box<ImplBlockDetails>>,
     materialization: Use,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::system_include_tokens;
+
+    #[test]
+    fn test_system_include_tokens() {
+        assert!(system_include_tokens("vendor/lib-2.h").is_some());
+        assert!(system_include_tokens("map").is_some());
+        assert!(system_include_tokens("my lib/foo.h").is_none());
+        assert!(system_include_tokens("bad\"quote.h").is_none());
+    }
+}
@@ -17,7 +17,7 @@ use crate::types::make_ident;
 use proc_macro2::Ident;
 use quote::quote;
 use syn::parse::Parser;
-use syn::{parse_quote, Field, GenericParam, ItemStruct};
+use syn::{parse_quote, Field, GenericParam, ItemStruct, ItemUnion};
 
 pub(crate) fn new_non_pod_struct(id: Ident) -> ItemStruct {
     let mut s = parse_quote! {
@@ -28,6 +28,20 @@ pub(crate) fn new_non_pod_struct(id: Ident) -> ItemStruct {
     s
 }
 
+/// Unions which can't be POD are represented just like any other
+/// non-POD type, as an opaque struct.
+pub(crate) fn make_non_pod_union(u: ItemUnion) -> ItemStruct {
+    let id = u.ident;
+    let mut s: ItemStruct = parse_quote! {
+        pub struct #id {
+        }
+    };
+    s.attrs = u.attrs;
+    s.generics = u.generics;
+    make_non_pod(&mut s);
+    s
+}
+
 pub(crate) fn make_non_pod(s: &mut ItemStruct) {
     // Keep only doc attrs, plus add a #[repr(C,packed)].
     // Thanks to dtolnay@ for this explanation of why the following
@@ -0,0 +1,77 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quote::quote;
+use syn::{parse_quote, ImplItem, Item};
+
+use crate::{
+    conversion::api::PairTypes,
+    types::{make_ident, QualifiedName},
+};
+
+use super::unqualify::unqualify_type;
+
+/// Generates a Rust getter for each element of a `std::pair`, calling
+/// the C++ helper functions generated alongside its typedef. If functions
+/// can return it as a Rust tuple, also generates the struct, shared with
+/// cxx, into which C++ unpacks it. Returns global items and items for the
+/// cxx::bridge.
+pub(super) fn generate_pair_helpers(
+    name: &QualifiedName,
+    pair_types: &PairTypes,
+) -> (Vec<Item>, Vec<Item>) {
+    let id = name.get_final_ident();
+    let fulltypath = name.get_bindgen_path_idents();
+    let pair = quote! { #(#fulltypath)::* };
+    let pair_name = id.to_string();
+    let mut bridge_fns = Vec::new();
+    let mut getters: Vec<ImplItem> = Vec::new();
+    for (field, ty) in [("first", &pair_types.first), ("second", &pair_types.second)].iter() {
+        let getter = make_ident(field);
+        let helper = make_ident(PairTypes::helper_name(&pair_name, field));
+        let bridge_ty = unqualify_type(ty.as_ref().clone());
+        bridge_fns.push(quote! {
+            fn #helper(pair: &#id) -> &#bridge_ty;
+        });
+        let doc = format!("Returns the {} element of the pair.", field);
+        getters.push(parse_quote! {
+            #[doc = #doc]
+            pub fn #getter(&self) -> &#ty {
+                cxxbridge::#helper(self)
+            }
+        });
+    }
+    let mut bridge_items = vec![Item::Verbatim(quote! {
+        unsafe extern "C++" {
+            #(#bridge_fns)*
+        }
+    })];
+    if pair_types.marshallable {
+        let struct_name = make_ident(PairTypes::helper_name(&pair_name, "struct"));
+        let first = unqualify_type(PairTypes::field_type(&pair_types.first));
+        let second = unqualify_type(PairTypes::field_type(&pair_types.second));
+        bridge_items.push(Item::Struct(parse_quote! {
+            struct #struct_name {
+                first: #first,
+                second: #second
+            }
+        }));
+    }
+    let global_items = vec![Item::Impl(parse_quote! {
+        impl #pair {
+            #(#getters)*
+        }
+    })];
+    (global_items, bridge_items)
+}
@@ -56,7 +56,7 @@ where
     new_pun
 }
 
-fn unqualify_type(typ: Type) -> Type {
+pub(crate) fn unqualify_type(typ: Type) -> Type {
     match typ {
         Type::Path(typ) => Type::Path(unqualify_type_path(typ)),
         Type::Reference(mut typeref) => {
@@ -67,6 +67,10 @@ fn unqualify_type(typ: Type) -> Type {
             typeptr.elem = unqualify_boxed_type(typeptr.elem);
             Type::Ptr(typeptr)
         }
+        Type::Array(mut typearr) => {
+            typearr.elem = unqualify_boxed_type(typearr.elem);
+            Type::Array(typearr)
+        }
         _ => typ,
     }
 }
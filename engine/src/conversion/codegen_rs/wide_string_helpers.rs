@@ -0,0 +1,99 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quote::quote;
+use syn::{parse_quote, Item, Type};
+
+use crate::{
+    conversion::api::WideString,
+    types::{make_ident, QualifiedName},
+};
+
+/// Generates Rust functions to convert a `std::u16string` or
+/// `std::wstring` to and from Rust strings and code units, calling the
+/// C++ helper functions generated alongside its typedef.
+/// Returns global items and items for the cxx::bridge.
+pub(super) fn generate_wide_string_helpers(
+    name: &QualifiedName,
+    kind: &WideString,
+) -> (Vec<Item>, Vec<Item>) {
+    let id = name.get_final_ident();
+    let fulltypath = name.get_bindgen_path_idents();
+    let string = quote! { #(#fulltypath)::* };
+    let string_name = id.to_string();
+    let to_units = make_ident(WideString::helper_name(&string_name, "to_units"));
+    let from_units = make_ident(WideString::helper_name(&string_name, "from_units"));
+    let (unit, decode, encode): (Type, _, _) = match kind {
+        WideString::U16String => (
+            parse_quote! { u16 },
+            quote! { String::from_utf16_lossy(&units) },
+            quote! { s.encode_utf16().collect() },
+        ),
+        // wchar_t is UTF-16 on Windows and UTF-32 elsewhere.
+        WideString::WString => (
+            parse_quote! { u32 },
+            quote! {
+                if cfg!(windows) {
+                    let units: Vec<u16> = units.into_iter().map(|unit| unit as u16).collect();
+                    String::from_utf16_lossy(&units)
+                } else {
+                    units
+                        .into_iter()
+                        .map(|unit| char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect()
+                }
+            },
+            quote! {
+                if cfg!(windows) {
+                    s.encode_utf16().map(u32::from).collect()
+                } else {
+                    s.chars().map(u32::from).collect()
+                }
+            },
+        ),
+    };
+    let bridge_items = vec![Item::Verbatim(quote! {
+        unsafe extern "C++" {
+            fn #to_units(s: &#id) -> UniquePtr<CxxVector<#unit>>;
+            fn #from_units(units: &[#unit]) -> UniquePtr<#id>;
+        }
+    })];
+    let global_items = vec![Item::Impl(parse_quote! {
+        impl #string {
+            /// Copies the code units in this string into a `Vec`.
+            pub fn to_units(&self) -> Vec<#unit> {
+                cxxbridge::#to_units(self).iter().copied().collect()
+            }
+
+            /// Converts this string to a Rust `String`, replacing any
+            /// invalid code units with U+FFFD.
+            pub fn to_string_lossy(&self) -> String {
+                let units = self.to_units();
+                #decode
+            }
+
+            /// Creates a new string containing a copy of some code units.
+            pub fn new_from_units(units: &[#unit]) -> cxx::UniquePtr<Self> {
+                cxxbridge::#from_units(units)
+            }
+
+            /// Creates a new string containing a copy of a Rust string.
+            pub fn new_from_str(s: &str) -> cxx::UniquePtr<Self> {
+                let units: Vec<#unit> = #encode;
+                Self::new_from_units(&units)
+            }
+        }
+    })];
+    (global_items, bridge_items)
+}
@@ -45,6 +45,10 @@ pub enum ConvertError {
     TypeContainingForwardDeclaration(QualifiedName),
     Blocked(QualifiedName),
     UnusedTemplateParam,
+    InvalidSliceParams(String),
+    UnsupportedCallback(String),
+    VariadicTemplateArgsUnknown(QualifiedName),
+    ConcreteNameCollision(String, String),
 }
 
 fn format_maybe_identifier(id: &Option<Ident>) -> String {
@@ -77,11 +81,15 @@ impl Display for ConvertError {
             ConvertError::UnexpectedUseStatement(maybe_ident) => write!(f, "Unexpected 'use' statement encountered: {}", format_maybe_identifier(maybe_ident))?,
             ConvertError::TemplatedTypeContainingNonPathArg(tn) => write!(f, "Type {} was parameterized over something complex which we don't yet support", tn)?,
             ConvertError::InvalidPointee => write!(f, "Pointer pointed to something unsupported")?,
-            ConvertError::DidNotGenerateAnything(directive) => write!(f, "The 'generate' or 'generate_pod' directive for '{}' did not result in any code being generated. Perhaps this was mis-spelled or you didn't qualify the name with any namespaces? Otherwise please report a bug.", directive)?,
+            ConvertError::DidNotGenerateAnything(directive) => write!(f, "The 'generate', 'generate_pod' or 'generate_pattern' directive for '{}' did not result in any code being generated. Perhaps this was mis-spelled or you didn't qualify the name with any namespaces? Otherwise please report a bug.", directive)?,
             ConvertError::UnacceptableSpecialNamespaceType(tn) => write!(f, "The type '{}' would require special support from cxx/autocxx, but is not yet supported", tn.to_cpp_name())?,
             ConvertError::TypeContainingForwardDeclaration(tn) => write!(f, "Found an attempt at using a forward declaration ({}) inside a templated cxx type such as UniquePtr or CxxVector", tn.to_cpp_name())?,
             ConvertError::Blocked(tn) => write!(f, "Found an attempt at using a type marked as blocked! ({})", tn.to_cpp_name())?,
             ConvertError::UnusedTemplateParam => write!(f, "This function or method uses a type where one of the template parameters was incomprehensible to bindgen/autocxx - probably because it uses template specialization.")?,
+            ConvertError::InvalidSliceParams(fn_name) => write!(f, "A 'slice' directive for {} must name a pointer parameter (to a POD type) followed immediately by a length parameter", fn_name)?,
+            ConvertError::UnsupportedCallback(fn_name) => write!(f, "Function {} takes a callback which can't be created from a Rust closure. Function pointers must be accompanied by a 'void*' user data parameter, either immediately before or immediately after, and callback arguments and return values must be POD types passed by value", fn_name)?,
+            ConvertError::VariadicTemplateArgsUnknown(tn) => write!(f, "bindgen doesn't tell us the type arguments of variadic templates such as {}, so it's not yet supported", tn.to_cpp_name())?,
+            ConvertError::ConcreteNameCollision(rust_name, cpp_definition) => write!(f, "The name {} for the template instantiation {} clashes with a name chosen using concrete!. Please choose a different name.", rust_name, cpp_definition)?,
         }
         Ok(())
     }
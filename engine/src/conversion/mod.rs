@@ -24,7 +24,7 @@ mod parse;
 mod utilities;
 
 use analysis::fun::FnAnalyzer;
-use autocxx_parser::TypeConfig;
+use autocxx_parser::{CppInclusion, TypeConfig};
 pub(crate) use codegen_cpp::CppCodeGenerator;
 pub(crate) use codegen_cpp::CppCodegenResults;
 pub(crate) use convert_error::ConvertError;
@@ -54,7 +54,7 @@ use self::{
 /// if the bindgen output is not as expected. It may be in future that
 /// we need to be a bit more graceful, but for now, that's OK.
 pub(crate) struct BridgeConverter<'a> {
-    include_list: &'a [String],
+    include_list: &'a [CppInclusion],
     type_config: &'a TypeConfig,
 }
 
@@ -65,7 +65,7 @@ pub(crate) struct CodegenResults {
 }
 
 impl<'a> BridgeConverter<'a> {
-    pub fn new(include_list: &'a [String], type_config: &'a TypeConfig) -> Self {
+    pub fn new(include_list: &'a [CppInclusion], type_config: &'a TypeConfig) -> Self {
         Self {
             include_list,
             type_config,
@@ -132,6 +132,7 @@ impl<'a> BridgeConverter<'a> {
                     analyzed_apis,
                     self.include_list,
                     bindgen_mod,
+                    self.type_config,
                 );
                 Ok(CodegenResults { rs, cpp })
             }
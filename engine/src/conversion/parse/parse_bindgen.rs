@@ -21,14 +21,17 @@ use crate::conversion::{
 };
 use crate::{
     conversion::{
-        api::{ApiDetail, ParseResults, TypedefKind, UnanalyzedApi},
+        api::{ApiDetail, NullAnalysis, ParseResults, TypedefKind, UnanalyzedApi},
         ConvertError,
     },
+    known_types::{known_types, BINDGEN_BITFIELD_UNIT},
     types::Namespace,
     types::QualifiedName,
 };
 use autocxx_parser::TypeConfig;
-use syn::{parse_quote, Fields, Item, Type, TypePath, UseTree};
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{parse_quote, Fields, ImplItem, Item, Type, TypePath, UseTree};
 
 use super::{super::utilities::generate_utilities, type_converter::TypeConverter};
 
@@ -65,7 +68,9 @@ impl<'a> ParseBindgen<'a> {
         exclude_utilities: bool,
     ) -> Result<ParseResults<'a>, ConvertError> {
         let items = Self::find_items_in_root(items)?;
-        if !exclude_utilities {
+        if exclude_utilities {
+            self.results.type_converter.exclude_utilities();
+        } else {
             generate_utilities(&mut self.results.apis);
         }
         let root_ns = Namespace::new();
@@ -124,6 +129,16 @@ impl<'a> ParseBindgen<'a> {
                 if s.ident.to_string().ends_with("__bindgen_vtable") {
                     return Ok(());
                 }
+                if s.ident == BINDGEN_BITFIELD_UNIT {
+                    self.results.apis.push(UnanalyzedApi {
+                        name: QualifiedName::new(ns, s.ident.clone()),
+                        deps: HashSet::new(),
+                        detail: ApiDetail::BitfieldUnit {
+                            items: vec![Item::Struct(s)],
+                        },
+                    });
+                    return Ok(());
+                }
                 let tyname = QualifiedName::new(ns, s.ident.clone());
                 let is_forward_declaration = Self::spot_forward_declaration(&s.fields);
                 // cxx::bridge can't cope with type aliases to generic
@@ -137,12 +152,20 @@ impl<'a> ParseBindgen<'a> {
                 self.latest_virtual_this_type = Some(tyname);
                 Ok(())
             }
+            Item::Union(u) => {
+                // Whether this is POD is decided later, just as for
+                // structs. If it isn't, it becomes an opaque type.
+                let tyname = QualifiedName::new(ns, u.ident.clone());
+                self.parse_type(tyname.clone(), false, HashSet::new(), Some(Item::Union(u)));
+                self.latest_virtual_this_type = Some(tyname);
+                Ok(())
+            }
             Item::Enum(e) => {
                 let tyname = QualifiedName::new(ns, e.ident.clone());
                 self.parse_type(tyname, false, HashSet::new(), Some(Item::Enum(e)));
                 Ok(())
             }
-            Item::Impl(imp) => {
+            Item::Impl(mut imp) => {
                 // We *mostly* ignore all impl blocks generated by bindgen.
                 // Methods also appear in 'extern "C"' blocks which
                 // we will convert instead. At that time we'll also construct
@@ -150,6 +173,32 @@ impl<'a> ParseBindgen<'a> {
                 // We do however record which methods were spotted, since
                 // we have no other way of working out which functions are
                 // static methods vs plain functions.
+                // The exceptions are bitfields, whose accessors exist only
+                // in Rust, so we keep those, and the impls of the type in
+                // which bindgen stores them.
+                let ty_id = match imp.self_ty.as_ref() {
+                    Type::Path(typ) => typ.path.segments.last().unwrap().ident.clone(),
+                    _ => return Ok(()),
+                };
+                let tyname = QualifiedName::new(ns, ty_id.clone());
+                if ty_id == BINDGEN_BITFIELD_UNIT {
+                    if let Some(ApiDetail::BitfieldUnit { items }) = self.find_api_detail(&tyname) {
+                        items.push(Item::Impl(imp));
+                    }
+                    return Ok(());
+                }
+                let (mut bitfield_accessors, other_items): (Vec<_>, Vec<_>) =
+                    imp.items.into_iter().partition(Self::is_bitfield_accessor);
+                if !bitfield_accessors.is_empty() {
+                    if let Some(ApiDetail::Type {
+                        bitfield_accessors: accessors,
+                        ..
+                    }) = self.find_api_detail(&tyname)
+                    {
+                        accessors.append(&mut bitfield_accessors);
+                    }
+                }
+                imp.items = other_items;
                 mod_converter.convert_impl_items(imp);
                 Ok(())
             }
@@ -234,6 +283,30 @@ impl<'a> ParseBindgen<'a> {
             }
             Item::Type(mut ity) => {
                 let tyname = QualifiedName::new(ns, ity.ident.clone());
+                // bindgen may give us typedefs for types such as size_t.
+                // We substitute our own known type wherever they're used,
+                // but bindgen's structs may still refer to them, so keep
+                // them as typedefs to that known type.
+                if let Some(known_type) = known_types().known_type_type_path(&tyname) {
+                    ity.ty = Box::new(Type::Path(known_type));
+                    self.results.apis.push(UnanalyzedApi {
+                        name: tyname,
+                        deps: HashSet::new(),
+                        detail: ApiDetail::Typedef {
+                            payload: TypedefKind::Type(ity),
+                        },
+                    });
+                    return Ok(());
+                }
+                // bindgen represents some types which it can't understand as
+                // typedefs to arrays of integers, so we can't trust that a
+                // typedef to an array is really an array.
+                if let Type::Array(_) = ity.ty.as_ref() {
+                    return Err(ConvertErrorWithContext(
+                        ConvertError::UnknownType(ity.ty.to_token_stream().to_string()),
+                        Some(ErrorContext::Item(ity.ident)),
+                    ));
+                }
                 let type_conversion_results =
                     self.results
                         .type_converter
@@ -286,6 +359,52 @@ impl<'a> ParseBindgen<'a> {
             .any(|id| id == "_unused")
     }
 
+    /// Whether this is one of the getters or setters which bindgen
+    /// generates for bitfields, or the function it generates to
+    /// construct their storage.
+    /// bindgen's getters and setters act on a `self._bitfield_N` field,
+    /// while the constructor is named `new_bitfield_N`.
+    fn is_bitfield_accessor(item: &ImplItem) -> bool {
+        match item {
+            ImplItem::Method(m) => {
+                Self::is_numbered(&m.sig.ident.to_string(), "new_bitfield_")
+                    || Self::accesses_bitfield_unit(m.block.to_token_stream())
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether this token stream contains `self._bitfield_N`.
+    fn accesses_bitfield_unit(ts: TokenStream) -> bool {
+        let tokens: Vec<TokenTree> = ts.into_iter().collect();
+        tokens.windows(3).any(|w| {
+            matches!(w, [TokenTree::Ident(receiver), TokenTree::Punct(dot), TokenTree::Ident(field)]
+                if receiver == "self"
+                    && dot.as_char() == '.'
+                    && Self::is_numbered(&field.to_string(), "_bitfield_"))
+        }) || tokens.iter().any(|t| match t {
+            TokenTree::Group(g) => Self::accesses_bitfield_unit(g.stream()),
+            _ => false,
+        })
+    }
+
+    /// Whether `name` is `prefix` followed by a number.
+    fn is_numbered(name: &str, prefix: &str) -> bool {
+        match name.strip_prefix(prefix) {
+            Some(suffix) => !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()),
+            None => false,
+        }
+    }
+
+    /// Finds the details of an API we've already recorded.
+    fn find_api_detail(&mut self, name: &QualifiedName) -> Option<&mut ApiDetail<NullAnalysis>> {
+        self.results
+            .apis
+            .iter_mut()
+            .find(|api| &api.name == name)
+            .map(|api| &mut api.detail)
+    }
+
     fn add_opaque_type(&mut self, name: QualifiedName) {
         self.results.apis.push(UnanalyzedApi {
             name,
@@ -310,14 +429,20 @@ impl<'a> ParseBindgen<'a> {
         if self.type_config.is_on_blocklist(&name.to_cpp_name()) {
             return;
         }
+        let extern_cpp_type = self.type_config.get_extern_cpp_type(&name.to_cpp_name());
         let api = UnanalyzedApi {
             name: name.clone(),
             deps,
-            detail: if is_forward_declaration {
+            detail: if let Some(rust_path) = extern_cpp_type {
+                ApiDetail::ExternCppType {
+                    rust_path: rust_path.clone(),
+                }
+            } else if is_forward_declaration {
                 ApiDetail::ForwardDeclaration
             } else {
                 ApiDetail::Type {
                     bindgen_mod_item,
+                    bitfield_accessors: Vec::new(),
                     analysis: (),
                 }
             },
@@ -340,6 +465,13 @@ impl<'a> ParseBindgen<'a> {
                 ));
             }
         }
+        for pattern in self.type_config.allowlist_patterns() {
+            if !api_names.iter().any(|name| pattern.is_match(name)) {
+                return Err(ConvertError::DidNotGenerateAnything(
+                    pattern.as_str().into(),
+                ));
+            }
+        }
         Ok(())
     }
 }
@@ -13,16 +13,20 @@
 // limitations under the License.
 
 use crate::{
-    conversion::{api::UnanalyzedApi, codegen_cpp::type_to_cpp::type_to_cpp, ConvertError},
-    known_types::known_types,
-    types::{make_ident, Namespace, QualifiedName},
+    conversion::{
+        api::{ConcreteTypeHelpers, MapTypes, PairTypes, UnanalyzedApi, WideString},
+        codegen_cpp::type_to_cpp::type_to_cpp,
+        ConvertError,
+    },
+    known_types::{known_types, BINDGEN_BITFIELD_UNIT},
+    types::{array_len, make_ident, Namespace, QualifiedName},
 };
 use autocxx_parser::TypeConfig;
 use quote::ToTokens;
 use std::collections::{HashMap, HashSet};
 use syn::{
-    parse_quote, punctuated::Punctuated, GenericArgument, PathArguments, PathSegment, Type,
-    TypePath, TypePtr,
+    parse_quote, punctuated::Punctuated, GenericArgument, PathArguments, PathSegment, ReturnType,
+    Type, TypeBareFn, TypePath, TypePtr,
 };
 
 /// Results of some type conversion, annotated with a list of every type encountered,
@@ -77,7 +81,9 @@ pub(crate) struct TypeConverter<'a> {
     types_found: Vec<QualifiedName>,
     typedefs: HashMap<QualifiedName, Type>,
     concrete_templates: HashMap<String, QualifiedName>,
+    primitive_wrappers: HashMap<String, QualifiedName>,
     config: &'a TypeConfig,
+    generate_utilities: bool,
 }
 
 impl<'a> TypeConverter<'a> {
@@ -86,10 +92,18 @@ impl<'a> TypeConverter<'a> {
             types_found: Vec::new(),
             typedefs: HashMap::new(),
             concrete_templates: HashMap::new(),
+            primitive_wrappers: HashMap::new(),
             config,
+            generate_utilities: true,
         }
     }
 
+    /// Don't generate the helper functions which convert wide strings
+    /// to and from Rust strings.
+    pub(crate) fn exclude_utilities(&mut self) {
+        self.generate_utilities = false;
+    }
+
     pub(crate) fn push(&mut self, ty: QualifiedName) {
         self.types_found.push(ty);
     }
@@ -123,6 +137,9 @@ impl<'a> TypeConverter<'a> {
         types_to_allow_only_in_references_and_ptrs: &HashSet<QualifiedName>,
     ) -> Result<Annotated<Type>, ConvertError> {
         let result = match ty {
+            Type::Path(p) if Self::get_function_pointer(&p).is_some() => {
+                self.convert_function_pointer(Self::get_function_pointer(&p).unwrap().clone(), ns)?
+            }
             Type::Path(p) => {
                 let newp =
                     self.convert_type_path(p, ns, types_to_allow_only_in_references_and_ptrs)?;
@@ -165,6 +182,13 @@ impl<'a> TypeConverter<'a> {
                 self.convert_ptr_to_reference(ptr, ns)?
             }
             Type::Ptr(mut ptr) => {
+                // bindgen represents array parameters of unknown size as pointers
+                // to zero-length arrays. Treat them as the pointers they decay to.
+                if let Type::Array(arr) = ptr.elem.as_ref() {
+                    if array_len(arr) == Some(0) {
+                        ptr.elem = arr.elem.clone();
+                    }
+                }
                 crate::known_types::ensure_pointee_is_valid(&ptr)?;
                 let innerty = self.convert_boxed_type(ptr.elem, ns, false, &HashSet::new())?;
                 ptr.elem = innerty.ty;
@@ -175,6 +199,21 @@ impl<'a> TypeConverter<'a> {
                     true,
                 )
             }
+            Type::Array(mut arr) => {
+                let innerty = self.convert_boxed_type(
+                    arr.elem,
+                    ns,
+                    false,
+                    types_to_allow_only_in_references_and_ptrs,
+                )?;
+                arr.elem = innerty.ty;
+                Annotated::new(
+                    Type::Array(arr),
+                    innerty.types_encountered,
+                    innerty.extra_apis,
+                    innerty.requires_unsafe,
+                )
+            }
             _ => return Err(ConvertError::UnknownType(ty.to_token_stream().to_string())),
         };
         Ok(result)
@@ -254,26 +293,100 @@ impl<'a> TypeConverter<'a> {
 
         let mut extra_apis = Vec::new();
 
+        // bindgen's storage for bitfields is generic, but it's ours to use
+        // as-is in Rust, so it doesn't need a concrete type.
+        if tn.get_final_item() == BINDGEN_BITFIELD_UNIT {
+            return Ok(Annotated::new(Type::Path(typ), deps, extra_apis, false));
+        }
+
+        // Wide strings aren't templates as far as bindgen's concerned, but
+        // cxx doesn't know them either, so they need a concrete type too.
+        if known_types().is_wide_string(&tn) {
+            let helpers = if self.generate_utilities {
+                let kind = if tn.to_cpp_name() == "std::u16string" {
+                    WideString::U16String
+                } else {
+                    WideString::WString
+                };
+                Some(Annotated::new(
+                    ConcreteTypeHelpers::WideString(kind),
+                    HashSet::new(),
+                    Vec::new(),
+                    false,
+                ))
+            } else {
+                None
+            };
+            let (new_tn, api) = self.get_templated_typename(&Type::Path(typ), helpers)?;
+            extra_apis.extend(api);
+            deps.remove(&tn);
+            deps.insert(new_tn.clone());
+            return Ok(Annotated::new(
+                Type::Path(new_tn.to_type_path()),
+                deps,
+                extra_apis,
+                false,
+            ));
+        }
+
+        // bindgen doesn't give us the type arguments of variadic templates
+        // such as std::tuple and std::variant, without which there's
+        // nothing we can do.
+        if (known_types().is_tuple(&tn) || known_types().is_variant(&tn))
+            && Self::get_generic_args(&mut typ).is_none()
+        {
+            return Err(ConvertError::VariadicTemplateArgsUnknown(tn));
+        }
+
         // Finally let's see if it's generic.
         if let Some(last_seg) = Self::get_generic_args(&mut typ) {
-            if known_types().is_cxx_acceptable_generic(&tn) {
-                // this is a type of generic understood by cxx (e.g. CxxVector)
-                // so let's convert any generic type arguments. This recurses.
-                crate::known_types::confirm_inner_type_is_acceptable_generic_payload(
-                    &last_seg.arguments,
-                    &tn,
-                    types_to_allow_only_in_references_and_ptrs,
-                )?;
+            if known_types().is_cxx_acceptable_generic(&tn)
+                || known_types().is_optional(&tn)
+                || known_types().is_span(&tn)
+                || known_types().is_function(&tn)
+            {
+                // this is a type of generic understood by cxx (e.g. CxxVector),
+                // or one which our wrapper functions will unpack (std::optional,
+                // std::span, std::function), so let's convert any generic type
+                // arguments. This recurses.
+                if !known_types().is_function(&tn) {
+                    crate::known_types::confirm_inner_type_is_acceptable_generic_payload(
+                        &last_seg.arguments,
+                        &tn,
+                        types_to_allow_only_in_references_and_ptrs,
+                    )?;
+                }
                 if let PathArguments::AngleBracketed(ref mut ab) = last_seg.arguments {
                     let mut innerty = self.convert_punctuated(ab.args.clone(), ns)?;
                     ab.args = innerty.ty;
                     deps.extend(innerty.types_encountered.drain());
+                    if known_types().is_unique_ptr(&tn) {
+                        for arg in ab.args.iter_mut() {
+                            if let GenericArgument::Type(ty) = arg {
+                                if let Some((new_tn, api)) = self.get_primitive_wrapper(ty) {
+                                    extra_apis.extend(api);
+                                    *ty = Type::Path(new_tn.to_type_path());
+                                    deps.insert(new_tn);
+                                }
+                            }
+                        }
+                    }
                 }
             } else {
                 // Oh poop. It's a generic type which cxx won't be able to handle.
                 // We'll have to come up with a concrete type in both the cxx::bridge (in Rust)
                 // and a corresponding typedef in C++.
-                let (new_tn, api) = self.get_templated_typename(&Type::Path(typ))?;
+                let mut helpers = if known_types().is_map(&tn) {
+                    Some(self.convert_map_types(&last_seg.arguments, &tn, ns)?)
+                } else if known_types().is_pair(&tn) {
+                    Some(self.convert_pair_types(&last_seg.arguments, &tn, ns)?)
+                } else {
+                    None
+                };
+                if let Some(helpers) = helpers.as_mut() {
+                    extra_apis.append(&mut helpers.extra_apis);
+                }
+                let (new_tn, api) = self.get_templated_typename(&Type::Path(typ), helpers)?;
                 extra_apis.extend(api.into_iter());
                 deps.remove(&tn);
                 typ = new_tn.to_type_path();
@@ -283,6 +396,77 @@ impl<'a> TypeConverter<'a> {
         Ok(Annotated::new(Type::Path(typ), deps, extra_apis, false))
     }
 
+    /// bindgen represents C function pointers (and function types used as
+    /// template arguments, as in `std::function<void(int)>`) as
+    /// `Option<unsafe extern "C" fn(...)>`. Spot that.
+    fn get_function_pointer(typ: &TypePath) -> Option<&TypeBareFn> {
+        let segs: Vec<_> = typ.path.segments.iter().collect();
+        match segs.as_slice() {
+            [std, option, opt]
+                if std.ident == "std" && option.ident == "option" && opt.ident == "Option" =>
+            {
+                match &opt.arguments {
+                    PathArguments::AngleBracketed(ab) if ab.args.len() == 1 => {
+                        match ab.args.first() {
+                            Some(GenericArgument::Type(Type::BareFn(bf))) => Some(bf),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts the argument and return types of a function pointer,
+    /// yielding a plain `fn(A, B) -> R` which later stages can turn into
+    /// a Rust closure type.
+    fn convert_function_pointer(
+        &mut self,
+        bf: TypeBareFn,
+        ns: &Namespace,
+    ) -> Result<Annotated<Type>, ConvertError> {
+        if bf.variadic.is_some() {
+            return Err(ConvertError::UnsupportedType(
+                bf.to_token_stream().to_string(),
+            ));
+        }
+        let mut types_encountered = HashSet::new();
+        let mut extra_apis = Vec::new();
+        let mut inputs: Vec<Type> = Vec::new();
+        for arg in bf.inputs.into_iter() {
+            let mut innerty = self.convert_type(arg.ty, ns, false, &HashSet::new())?;
+            types_encountered.extend(innerty.types_encountered.drain());
+            extra_apis.append(&mut innerty.extra_apis);
+            inputs.push(innerty.ty);
+        }
+        let output = match bf.output {
+            ReturnType::Default => ReturnType::Default,
+            ReturnType::Type(arrow, ty) => {
+                let mut innerty = self.convert_boxed_type(ty, ns, false, &HashSet::new())?;
+                types_encountered.extend(innerty.types_encountered.drain());
+                extra_apis.append(&mut innerty.extra_apis);
+                ReturnType::Type(arrow, innerty.ty)
+            }
+        };
+        Ok(Annotated::new(
+            Type::BareFn(parse_quote! {
+                fn(#(#inputs),*) #output
+            }),
+            types_encountered,
+            extra_apis,
+            false,
+        ))
+    }
+
+    fn is_primitive(ty: &Type) -> bool {
+        match ty {
+            Type::Path(typ) => known_types().is_primitive(&QualifiedName::from_type_path(typ)),
+            _ => false,
+        }
+    }
+
     fn get_generic_args(typ: &mut TypePath) -> Option<&mut PathSegment> {
         match typ.path.segments.last_mut() {
             Some(s) if !s.arguments.is_empty() => Some(s),
@@ -342,6 +526,12 @@ impl<'a> TypeConverter<'a> {
         // headers; it manifests as &str in Rust but on the C++ side it must
         // be a plain value. We should detect and abort.
         Ok(elem.map(|elem| match mutability {
+            // Arrays and primitives are Unpin, so there's no need for a Pin here.
+            Some(_) if matches!(elem.as_ref(), Type::Array(_)) || Self::is_primitive(&elem) => {
+                Type::Reference(parse_quote! {
+                    & #mutability #elem
+                })
+            }
             Some(_) => Type::Path(parse_quote! {
                 std::pin::Pin < & #mutability #elem >
             }),
@@ -351,32 +541,251 @@ impl<'a> TypeConverter<'a> {
         }))
     }
 
+    /// The type arguments of a templated type.
+    fn get_type_args(args: &PathArguments) -> Vec<&Type> {
+        match args {
+            PathArguments::AngleBracketed(ab) => ab
+                .args
+                .iter()
+                .filter_map(|arg| match arg {
+                    GenericArgument::Type(ty) => Some(ty),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Converts the key and value types of a `std::map` or
+    /// `std::unordered_map`, so that we can generate helper functions
+    /// which use them.
+    fn convert_map_types(
+        &mut self,
+        args: &PathArguments,
+        tn: &QualifiedName,
+        ns: &Namespace,
+    ) -> Result<Annotated<ConcreteTypeHelpers>, ConvertError> {
+        let (key, value) = match Self::get_type_args(args).as_slice() {
+            [key, value, ..] => (*key, *value),
+            _ => return Err(ConvertError::TemplatedTypeContainingNonPathArg(tn.clone())),
+        };
+        let mut key = self.convert_type(key.clone(), ns, false, &HashSet::new())?;
+        let mut value = self.convert_type(value.clone(), ns, false, &HashSet::new())?;
+        key.types_encountered
+            .extend(value.types_encountered.drain());
+        key.extra_apis.append(&mut value.extra_apis);
+        Ok(Annotated::new(
+            ConcreteTypeHelpers::Map(MapTypes {
+                key: Box::new(key.ty),
+                value: Box::new(value.ty),
+                ordered: tn.to_cpp_name() == "std::map",
+                value_is_pod: false,
+            }),
+            key.types_encountered,
+            key.extra_apis,
+            false,
+        ))
+    }
+
+    /// Converts the element types of a `std::pair`, so that we can
+    /// generate a getter for each.
+    fn convert_pair_types(
+        &mut self,
+        args: &PathArguments,
+        tn: &QualifiedName,
+        ns: &Namespace,
+    ) -> Result<Annotated<ConcreteTypeHelpers>, ConvertError> {
+        let (first, second) = match Self::get_type_args(args).as_slice() {
+            [first, second] => (*first, *second),
+            _ => return Err(ConvertError::TemplatedTypeContainingNonPathArg(tn.clone())),
+        };
+        let mut first = self.convert_type(first.clone(), ns, false, &HashSet::new())?;
+        let mut second = self.convert_type(second.clone(), ns, false, &HashSet::new())?;
+        first
+            .types_encountered
+            .extend(second.types_encountered.drain());
+        first.extra_apis.append(&mut second.extra_apis);
+        Ok(Annotated::new(
+            ConcreteTypeHelpers::Pair(PairTypes {
+                first: Box::new(first.ty),
+                second: Box::new(second.ty),
+                marshallable: false,
+            }),
+            first.types_encountered,
+            first.extra_apis,
+            false,
+        ))
+    }
+
     fn get_templated_typename(
         &mut self,
         rs_definition: &Type,
+        helpers: Option<Annotated<ConcreteTypeHelpers>>,
     ) -> Result<(QualifiedName, Option<UnanalyzedApi>), ConvertError> {
-        let count = self.concrete_templates.len();
         // We just use this as a hash key, essentially.
         let cpp_definition = type_to_cpp(rs_definition)?;
         let e = self.concrete_templates.get(&cpp_definition);
         match e {
             Some(tn) => Ok((tn.clone(), None)),
             None => {
-                let name = QualifiedName::new(
-                    &Namespace::new(),
-                    make_ident(&format!("AutocxxConcrete{}", count)),
-                );
+                let rust_name = match self.config.get_concrete_name(&cpp_definition) {
+                    Some(rust_name) if self.is_concrete_name_taken(rust_name) => {
+                        return Err(ConvertError::ConcreteNameCollision(
+                            rust_name.to_string(),
+                            cpp_definition,
+                        ))
+                    }
+                    Some(rust_name) => rust_name.to_string(),
+                    None => self.make_unique_concrete_name(&cpp_definition)?,
+                };
+                let name = QualifiedName::new(&Namespace::new(), make_ident(&rust_name));
                 self.concrete_templates
                     .insert(cpp_definition.clone(), name.clone());
+                // Our map and pair helper functions refer to the key, value
+                // and element types, so make sure we keep those.
+                let (deps, helpers) = match helpers {
+                    Some(helpers) => (helpers.types_encountered, Some(helpers.ty)),
+                    None => (HashSet::new(), None),
+                };
                 let api = UnanalyzedApi {
                     name: name.clone(),
-                    deps: HashSet::new(),
+                    deps,
                     detail: crate::conversion::api::ApiDetail::ConcreteType {
                         rs_definition: Box::new(rs_definition.clone()),
+                        helpers,
                     },
                 };
                 Ok((name, Some(api)))
             }
         }
     }
+
+    /// cxx can't hold primitives in a `UniquePtr`, so if this is a
+    /// primitive, return a newtype wrapper for it which it can hold.
+    /// On the C++ side, that's just a typedef for the primitive.
+    fn get_primitive_wrapper(
+        &mut self,
+        ty: &Type,
+    ) -> Option<(QualifiedName, Option<UnanalyzedApi>)> {
+        let payload_tn = match ty {
+            Type::Path(typ) => QualifiedName::from_type_path(typ),
+            _ => return None,
+        };
+        if !known_types().is_primitive(&payload_tn) {
+            return None;
+        }
+        let cpp_definition = payload_tn.to_cpp_name();
+        if let Some(tn) = self.primitive_wrappers.get(&cpp_definition) {
+            return Some((tn.clone(), None));
+        }
+        let name = QualifiedName::new(
+            &Namespace::new(),
+            make_ident(make_type_name("AutocxxPrimitive", &cpp_definition)),
+        );
+        self.primitive_wrappers.insert(cpp_definition, name.clone());
+        let api = UnanalyzedApi {
+            name: name.clone(),
+            deps: std::iter::once(payload_tn).collect(),
+            detail: crate::conversion::api::ApiDetail::PrimitiveWrapper {
+                payload: Box::new(ty.clone()),
+            },
+        };
+        Some((name, Some(api)))
+    }
+
+    /// Name a concrete instantiation of a template in a way which depends
+    /// only on its C++ spelling, not on the order in which we encounter it.
+    /// Only if that name is already taken by a different instantiation do
+    /// we add a suffix, which is a hash of the full C++ spelling, so it
+    /// doesn't depend on how many other clashes there are. It's an error
+    /// for the name to clash with any chosen using `concrete!`.
+    fn make_unique_concrete_name(&self, cpp_definition: &str) -> Result<String, ConvertError> {
+        let base_name = make_concrete_type_name(cpp_definition);
+        let name = if self.is_concrete_name_taken(&base_name) {
+            format!("{}{:08x}", base_name, hash_cpp_spelling(cpp_definition))
+        } else {
+            base_name
+        };
+        if self.config.is_chosen_concrete_name(&name) || self.is_concrete_name_taken(&name) {
+            Err(ConvertError::ConcreteNameCollision(
+                name,
+                cpp_definition.to_string(),
+            ))
+        } else {
+            Ok(name)
+        }
+    }
+
+    fn is_concrete_name_taken(&self, rust_name: &str) -> bool {
+        self.concrete_templates
+            .values()
+            .any(|tn| tn.get_final_item() == rust_name)
+    }
+}
+
+/// A hash of a C++ type spelling which, unlike `DefaultHasher`, is the
+/// same with every version of Rust. This is 32-bit FNV-1a.
+fn hash_cpp_spelling(cpp_definition: &str) -> u32 {
+    cpp_definition.bytes().fold(0x811c_9dc5, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Derives a Rust type name from the C++ spelling of a template
+/// instantiation, e.g. `std::map<int, Foo>` becomes
+/// `AutocxxConcreteStdMapIntFoo`.
+fn make_concrete_type_name(cpp_definition: &str) -> String {
+    make_type_name("AutocxxConcrete", cpp_definition)
+}
+
+/// Derives a Rust type name from a C++ type spelling, prefixed by `prefix`.
+fn make_type_name(prefix: &str, cpp_definition: &str) -> String {
+    let mut name = prefix.to_string();
+    let mut start_of_word = true;
+    for c in cpp_definition.chars() {
+        match c {
+            '*' => {
+                name.push_str("Ptr");
+                start_of_word = true;
+            }
+            '&' => {
+                name.push_str("Ref");
+                start_of_word = true;
+            }
+            c if c.is_ascii_alphanumeric() => {
+                name.push(if start_of_word {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                });
+                start_of_word = false;
+            }
+            _ => start_of_word = true,
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_cpp_spelling, make_concrete_type_name};
+
+    #[test]
+    fn test_concrete_type_names() {
+        assert_eq!(
+            make_concrete_type_name("std::map<int, Foo>"),
+            "AutocxxConcreteStdMapIntFoo"
+        );
+        assert_eq!(
+            make_concrete_type_name("Container<const uint32_t*>"),
+            "AutocxxConcreteContainerConstUint32TPtr"
+        );
+    }
+
+    #[test]
+    fn test_cpp_spelling_hash() {
+        assert_eq!(hash_cpp_spelling(""), 0x811c_9dc5);
+        assert_eq!(hash_cpp_spelling("a"), 0xe40c_292c);
+        assert_ne!(hash_cpp_spelling("A<B<C>>"), hash_cpp_spelling("A<B, C>"));
+    }
 }
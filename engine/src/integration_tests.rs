@@ -273,7 +273,7 @@ where
     let rs_path = write_rust_to_file(&rust_code);
 
     info!("Path is {:?}", tdir.path());
-    let build_results = crate::builder::build_to_custom_directory(
+    let mut build_results = crate::builder::build_to_custom_directory(
         &rs_path,
         &[tdir.path()],
         &extra_clang_args,
@@ -281,6 +281,7 @@ where
         None,
     )
     .map_err(TestError::AutoCxx)?;
+    let block_builds = build_results.take_block_builds();
     let mut b = build_results.0;
     let generated_rs_files = build_results.1;
 
@@ -297,13 +298,17 @@ where
 
     let target = rust_info::get().target_triple.unwrap();
 
-    if !cxx_code.is_empty() {
-        // Step 4: Write the C++ code snippet to a .cc file, along with a #include
-        //         of the header emitted in step 5.
-        let cxx_code = format!("#include \"{}\"\n{}", "input.h", cxx_code);
-        let cxx_path = write_to_file(&tdir, "input.cxx", &cxx_code);
-        b.file(cxx_path);
-    }
+    // Step 4: Write the C++ code snippet to a .cc file, along with a #include
+    //         of the header emitted in step 5. The file is written even if
+    //         empty, because autocxx-demo otherwise has nothing in it when
+    //         every include_cpp! block is built separately.
+    let cxx_code = if cxx_code.is_empty() {
+        String::new()
+    } else {
+        format!("#include \"{}\"\n{}", "input.h", cxx_code)
+    };
+    let cxx_path = write_to_file(&tdir, "input.cxx", &cxx_code);
+    b.file(cxx_path);
 
     let mut b = b
         .out_dir(&target_dir)
@@ -318,6 +323,30 @@ where
     b.include(tdir.path())
         .try_compile("autocxx-demo")
         .map_err(TestError::CppBuild)?;
+    // Blocks with their own compiler arguments are built separately, with
+    // the same settings as above; their own arguments are added last.
+    if !block_builds.is_empty() {
+        for mut block_build in block_builds {
+            let lib_name = block_build.lib_name().to_string();
+            let bb = block_build
+                .build_mut()
+                .out_dir(&target_dir)
+                .host(&target)
+                .target(&target)
+                .opt_level(1)
+                .flag("-std=c++14");
+            for f in extra_clang_args {
+                bb.flag(f);
+            }
+            bb.include(tdir.path());
+            block_build.compile().map_err(TestError::AutoCxx)?;
+            rust_code.append_all(quote! {
+                #[link(name=#lib_name)]
+                extern {}
+            });
+        }
+        write_rust_to_file(&rust_code);
+    }
     // Step 8: use the trybuild crate to build the Rust file.
     let r = get_builder().lock().unwrap().build(
         &target_dir,
@@ -432,7 +461,6 @@ fn test_take_i32() {
 }
 
 #[test]
-#[ignore] // because cxx doesn't support unique_ptrs to primitives.
 fn test_give_up_int() {
     let cxx = indoc! {"
         std::unique_ptr<uint32_t> give_up() {
@@ -451,7 +479,6 @@ fn test_give_up_int() {
 }
 
 #[test]
-#[ignore] // because we don't yet implement UniquePtr etc. for autocxx::c_int and friends
 fn test_give_up_ctype() {
     let cxx = indoc! {"
         std::unique_ptr<int> give_up() {
@@ -468,6 +495,52 @@ fn test_give_up_ctype() {
     run_test(cxx, hdr, rs, &["give_up"], &[]);
 }
 
+#[test]
+fn test_take_up_int() {
+    let cxx = indoc! {"
+        uint32_t take_up(std::unique_ptr<uint32_t> a) {
+            return *a;
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <memory>
+        uint32_t take_up(std::unique_ptr<uint32_t> a);
+    "};
+    let rs = quote! {
+        let a = cxx::UniquePtr::new(12u32.into());
+        assert_eq!(ffi::take_up(a), 12);
+    };
+    run_test(cxx, hdr, rs, &["take_up"], &[]);
+}
+
+#[test]
+fn test_int_out_param() {
+    let cxx = indoc! {"
+        void get_value(int32_t& out) {
+            out = 12;
+        }
+        void get_len(std::string s, uint32_t& out) {
+            out = s.length();
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <string>
+        void get_value(int32_t& out);
+        void get_len(std::string s, uint32_t& out);
+    "};
+    let rs = quote! {
+        let mut a = 0i32;
+        ffi::get_value(&mut a);
+        assert_eq!(a, 12);
+        let mut b = 0u32;
+        ffi::get_len("hello", &mut b);
+        assert_eq!(b, 5);
+    };
+    run_test(cxx, hdr, rs, &["get_value", "get_len"], &[]);
+}
+
 #[test]
 fn test_give_string_up() {
     let cxx = indoc! {"
@@ -1191,7 +1264,7 @@ fn test_make_up_with_args() {
 }
 
 #[test]
-#[ignore] // because we don't support unique_ptrs to primitives
+#[ignore] // because we don't support field access to opaque types
 fn test_make_up_int() {
     let cxx = indoc! {"
         Bob::Bob(uint32_t a) : b(a) {
@@ -3622,17 +3695,52 @@ fn test_ulong() {
 }
 
 #[test]
-fn test_typedef_to_ulong() {
+fn test_size_t() {
     let hdr = indoc! {"
         #include <cstddef>
         inline size_t daft(size_t a) { return a; }
     "};
     let rs = quote! {
-        assert_eq!(ffi::daft(autocxx::c_ulong(34)), autocxx::c_ulong(34));
+        assert_eq!(ffi::daft(34usize), 34usize);
     };
     run_test("", hdr, rs, &["daft"], &[]);
 }
 
+#[test]
+fn test_signed_sizes() {
+    let hdr = indoc! {"
+        #include <cstddef>
+        #include <cstdint>
+        #include <sys/types.h>
+        inline ptrdiff_t difference(ptrdiff_t a, ptrdiff_t b) { return a - b; }
+        inline ssize_t negate(ssize_t a) { return -a; }
+        inline intptr_t twice(intptr_t a) { return a * 2; }
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::difference(3isize, 5isize), -2isize);
+        assert_eq!(ffi::negate(4isize), -4isize);
+        assert_eq!(ffi::twice(-5isize), -10isize);
+    };
+    run_test("", hdr, rs, &["difference", "negate", "twice"], &[]);
+}
+
+#[test]
+fn test_size_t_in_pod() {
+    let hdr = indoc! {"
+        #include <cstddef>
+        struct Span {
+            size_t offset;
+            size_t length;
+        };
+        inline size_t end(Span s) { return s.offset + s.length; }
+    "};
+    let rs = quote! {
+        let s = ffi::Span { offset: 3, length: 4 };
+        assert_eq!(ffi::end(s), 7);
+    };
+    run_test("", hdr, rs, &["end"], &["Span"]);
+}
+
 #[test]
 fn test_generate_typedef_to_ulong() {
     let hdr = indoc! {"
@@ -4307,7 +4415,6 @@ fn test_private_constructor_make_unique() {
 }
 
 #[test]
-#[ignore] // https://github.com/google/autocxx/issues/266
 fn test_take_array() {
     let hdr = indoc! {"
     #include <cstdint>
@@ -4317,14 +4424,85 @@ fn test_take_array() {
     "};
     let rs = quote! {
         let c: [u32; 4usize] = [ 10, 20, 30, 40 ];
-        let c = c as *const [_];
         assert_eq!(ffi::take_array(&c), 40);
     };
     run_test("", hdr, rs, &["take_array"], &[]);
 }
 
 #[test]
-fn test_union_ignored() {
+fn test_take_mut_array() {
+    let hdr = indoc! {"
+    #include <cstdint>
+    inline void double_array(int32_t a[3]) {
+        for (int i = 0; i < 3; i++) {
+            a[i] *= 2;
+        }
+    }
+    "};
+    let rs = quote! {
+        let mut c: [i32; 3usize] = [ 1, 2, 3 ];
+        ffi::double_array(&mut c);
+        assert_eq!(c, [2, 4, 6]);
+    };
+    run_test("", hdr, rs, &["double_array"], &[]);
+}
+
+#[test]
+fn test_pod_with_array_field() {
+    let hdr = indoc! {"
+    #include <cstdint>
+    struct A {
+        int32_t values[4];
+        uint8_t grid[2][3];
+    };
+    inline int32_t sum(const A& a) {
+        return a.values[0] + a.values[1] + a.values[2] + a.values[3] + a.grid[1][2];
+    }
+    "};
+    let rs = quote! {
+        let a = ffi::A {
+            values: [1, 2, 3, 4],
+            grid: [[0, 0, 0], [0, 0, 5]],
+        };
+        assert_eq!(ffi::sum(&a), 15);
+    };
+    run_test("", hdr, rs, &["sum"], &["A"]);
+}
+
+#[test]
+fn test_union_pod() {
+    let hdr = indoc! {"
+    #include <cstdint>
+    union A {
+        uint32_t a;
+        float b;
+    };
+    struct B {
+        A u;
+        uint32_t c;
+    };
+    inline uint32_t get_a(A a) {
+        return a.a;
+    }
+    inline uint32_t sum(const B& b) {
+        return b.u.a + b.c;
+    }
+    "};
+    let rs = quote! {
+        let a = ffi::A { a: 5 };
+        assert_eq!(ffi::get_a(a), 5);
+        let b = ffi::B {
+            u: ffi::A { a: 2 },
+            c: 3,
+        };
+        assert_eq!(ffi::sum(&b), 5);
+        assert_eq!(unsafe { b.u.a }, 2);
+    };
+    run_test("", hdr, rs, &["get_a", "sum"], &["A", "B"]);
+}
+
+#[test]
+fn test_union_nonpod() {
     let hdr = indoc! {"
     #include <cstdint>
     union A {
@@ -4532,7 +4710,6 @@ fn test_defines_effective() {
 }
 
 #[test]
-#[ignore] // https://github.com/google/autocxx/issues/227
 fn test_function_pointer_template() {
     let hdr = indoc! {"
         typedef int a;
@@ -4664,179 +4841,1752 @@ fn test_string_transparent_static_method() {
     run_test("", hdr, rs, &["A"], &[]);
 }
 
-fn find_ffi_items(f: syn::File) -> Result<Vec<Item>, TestError> {
-    Ok(f.items
-        .into_iter()
-        .filter_map(|i| match i {
-            Item::Mod(itm) => Some(itm),
-            _ => None,
-        })
-        .next()
-        .ok_or(TestError::RsCodeExaminationFail)?
-        .content
-        .ok_or(TestError::RsCodeExaminationFail)?
-        .1)
-}
-
-/// Generates a closure which can be used to ensure that the given symbol
-/// is mentioned in the output and has documentation attached.
-/// The idea is that this is what we do in cases where we can't generate code properly.
-fn make_error_finder(error_symbol: &str) -> Box<dyn FnOnce(syn::File) -> Result<(), TestError>> {
-    let error_symbol = error_symbol.to_string();
-    Box::new(move |f| {
-        let ffi_items = find_ffi_items(f)?;
-        // Ensure there's some kind of struct entry for this symboll
-        let foo = ffi_items
-            .into_iter()
-            .filter_map(|i| match i {
-                Item::Struct(its) if its.ident.to_string() == error_symbol => Some(its),
-                _ => None,
-            })
-            .next()
-            .ok_or(TestError::RsCodeExaminationFail)?;
-        // Ensure doc attribute
-        foo.attrs
-            .into_iter()
-            .filter(|a| {
-                a.path
-                    .get_ident()
-                    .filter(|p| p.to_string() == "doc")
-                    .is_some()
-            })
-            .next()
-            .ok_or(TestError::RsCodeExaminationFail)?;
-        Ok(())
-    })
-}
-
 #[test]
-fn test_error_generated_for_static_data() {
+fn test_generate_ns() {
     let hdr = indoc! {"
         #include <cstdint>
-        struct A {
-            A() {}
-            uint32_t a;
-        };
-        static A FOO = A();
+        namespace A {
+            namespace B {
+                struct Bob {
+                    uint32_t a;
+                };
+                inline uint32_t get_a(const Bob& bob) { return bob.a; }
+            }
+            inline uint32_t give_int() { return 5; }
+        }
+        namespace C {
+            inline uint32_t take_int(uint32_t a) { return a + 1; }
+        }
     "};
-    let rs = quote! {};
+    let rs = quote! {
+        let b = ffi::A::B::Bob { a: 12 };
+        assert_eq!(ffi::A::B::get_a(&b), 12);
+        assert_eq!(ffi::A::give_int(), 5);
+    };
+    // Nothing from namespace C should have been generated.
+    let checker = Box::new(|f: syn::File| {
+        let mut ts = TokenStream::new();
+        f.to_tokens(&mut ts);
+        if ts.to_string().contains("take_int") {
+            Err(TestError::RsCodeExaminationFail)
+        } else {
+            Ok(())
+        }
+    });
     run_test_ex(
         "",
         hdr,
         rs,
-        &["FOO"],
         &[],
-        None,
+        &["A::B::Bob"],
+        Some(quote! { generate_ns!("A") }),
         &[],
-        Some(make_error_finder("FOO")),
+        Some(checker),
     );
 }
 
 #[test]
-fn test_error_generated_for_array_dependent_function() {
+fn test_generate_pattern() {
     let hdr = indoc! {"
         #include <cstdint>
-        #include <functional>
-        inline void take_func(std::function<bool(const uint32_t number)>) {
-        }
+        inline uint32_t get_a() { return 1; }
+        inline uint32_t get_b() { return 2; }
+        inline uint32_t set_c() { return 3; }
     "};
-    let rs = quote! {};
+    let rs = quote! {
+        assert_eq!(ffi::get_a(), 1);
+        assert_eq!(ffi::get_b(), 2);
+    };
+    let checker = Box::new(|f: syn::File| {
+        let mut ts = TokenStream::new();
+        f.to_tokens(&mut ts);
+        if ts.to_string().contains("set_c") {
+            Err(TestError::RsCodeExaminationFail)
+        } else {
+            Ok(())
+        }
+    });
     run_test_ex(
         "",
         hdr,
         rs,
-        &["take_func"],
         &[],
-        None,
         &[],
-        Some(make_error_finder("take_func")),
+        Some(quote! { generate_pattern!("get_.*") }),
+        &[],
+        Some(checker),
     );
 }
 
 #[test]
-fn test_error_generated_for_array_dependent_method() {
+fn test_block_pattern() {
     let hdr = indoc! {"
         #include <cstdint>
-        #include <functional>
-        struct A {
-            void take_func(std::function<bool(const uint32_t number)>) {
-            }
+        struct FooImpl {
+            uint32_t a;
         };
+        inline uint32_t get_a() { return 1; }
+        inline uint32_t take_foo(const FooImpl& foo) { return foo.a; }
     "};
-    let rs = quote! {};
+    let rs = quote! {
+        assert_eq!(ffi::get_a(), 1);
+    };
     run_test_ex(
         "",
         hdr,
         rs,
-        &["A"],
+        &["get_a", "take_foo"],
         &[],
-        None,
+        Some(quote! { block_pattern!(".*Impl") }),
         &[],
-        Some(make_string_finder(
-            ["take_func", "couldn't be generated"].to_vec(),
-        )),
+        None,
     );
 }
 
-/// Returns a closure which simply hunts for a given string in the results
-fn make_string_finder<'a>(
-    error_texts: Vec<&str>,
-) -> Box<dyn FnOnce(syn::File) -> Result<(), TestError> + '_> {
-    Box::new(|f| {
-        let mut ts = TokenStream::new();
-        f.to_tokens(&mut ts);
-        let toks = ts.to_string();
-        for msg in error_texts {
-            if !toks.contains(msg) {
-                return Err(TestError::RsCodeExaminationFail);
-            };
+#[test]
+fn test_rename_function() {
+    let cxx = indoc! {"
+        namespace A {
+            uint32_t get_thing_2() { return 4; }
         }
-        Ok(())
-    })
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        namespace A {
+            uint32_t get_thing_2();
+        }
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::A::thing(), 4);
+    };
+    run_test_ex(
+        cxx,
+        hdr,
+        rs,
+        &["A::get_thing_2"],
+        &[],
+        Some(quote! { rename!("A::get_thing_2", "thing") }),
+        &[],
+        None,
+    );
 }
 
 #[test]
-fn test_doc_passthru() {
+fn test_rename_type_and_method() {
     let hdr = indoc! {"
         #include <cstdint>
-        /// Elephants!
-        struct A {
-            uint32_t a;
-        };
-        /// Giraffes!
-        struct B {
-            uint32_t a;
-        };
-        /// Rhinos!
-        inline uint32_t get_a() { return 3; }
+        namespace A {
+            struct CFooBarImpl {
+                CFooBarImpl() : a(3) {}
+                uint32_t get_thing_2() const { return a; }
+                uint32_t a;
+            };
+        }
     "};
-    let rs = quote! {};
+    let rs = quote! {
+        let foo: cxx::UniquePtr<ffi::A::FooBar> = ffi::A::FooBar::make_unique();
+        assert_eq!(foo.thing(), 3);
+    };
     run_test_ex(
         "",
         hdr,
         rs,
-        &["A", "get_a"],
-        &["B"],
-        None,
+        &["A::CFooBarImpl"],
         &[],
-        Some(make_string_finder(
-            ["Giraffes", "Elephants", "Rhinos"].to_vec(),
-        )),
+        Some(quote! {
+            rename!("A::CFooBarImpl", "FooBar")
+            rename!("A::CFooBarImpl::get_thing_2", "thing")
+        }),
+        &[],
+        None,
     );
 }
 
 #[test]
-fn test_closure() {
-    // Ensuring presence of this closure doesn't break other things
+fn test_extern_cpp_type() {
     let hdr = indoc! {"
-    #include <functional>
-    #include <cstdint>
-
-    inline bool take_closure(std::function<bool(const uint32_t number)> fn) {
-        return fn(5);
-    }
-    inline uint32_t get_a() {
-        return 3;
+        #include <cstdint>
+        namespace A {
+            struct Widget {
+                uint32_t a;
+            };
+            inline uint32_t get_a(const Widget& w) { return w.a; }
+        }
+    "};
+    let rs = |hdr| {
+        let hexathorpe = Token![#](Span::call_site());
+        quote! {
+            use autocxx::include_cpp;
+            // Stands in for bindings generated by some other crate.
+            mod other {
+                #[repr(C)]
+                pub struct Widget {
+                    pub a: u32,
+                }
+                unsafe impl cxx::ExternType for Widget {
+                    type Id = cxx::type_id!("A::Widget");
+                    type Kind = cxx::kind::Trivial;
+                }
+            }
+            include_cpp! {
+                #hexathorpe include #hdr
+                safety!(unsafe_ffi)
+                generate!("A::get_a")
+                extern_cpp_type!("A::Widget", crate::other::Widget)
+            }
+            fn main() {
+                let w = other::Widget { a: 12 };
+                assert_eq!(ffi::A::get_a(&w), 12);
+            }
+        }
+    };
+    do_run_test_manual("", hdr, rs, &[], None).unwrap();
+}
+
+#[test]
+fn test_concrete_name_directive() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        template<typename TY>
+        struct Container {
+            Container(TY a_) : a(a_) {}
+            TY a;
+        };
+        inline Container<char> make_thingy() {
+            Container<char> a('a');
+            return a;
+        }
+        inline uint32_t take_thingy(Container<char> a) {
+            return a.a;
+        }
+    "};
+    let rs = quote! {
+        let a: cxx::UniquePtr<ffi::CharContainer> = ffi::make_thingy();
+        assert_eq!(ffi::take_thingy(a), 'a' as u32)
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["take_thingy", "make_thingy"],
+        &[],
+        Some(quote! { concrete!("Container<char>", "CharContainer") }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_concrete_default_name() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        template<typename TY>
+        struct Container {
+            Container(TY a_) : a(a_) {}
+            TY a;
+        };
+        inline Container<uint16_t> make_short_thingy() {
+            Container<uint16_t> a(3);
+            return a;
+        }
+        inline Container<char> make_thingy() {
+            Container<char> a('a');
+            return a;
+        }
+    "};
+    let rs = quote! {
+        let _a: cxx::UniquePtr<ffi::AutocxxConcreteContainerChar> = ffi::make_thingy();
+        let _b: cxx::UniquePtr<ffi::AutocxxConcreteContainerUint16T> = ffi::make_short_thingy();
+    };
+    run_test("", hdr, rs, &["make_thingy", "make_short_thingy"], &[]);
+}
+
+#[test]
+fn test_concrete_name_collision() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        template<typename TY>
+        struct Container {
+            Container(TY a_) : a(a_) {}
+            TY a;
+        };
+        inline Container<uint16_t> make_short_thingy() {
+            Container<uint16_t> a(3);
+            return a;
+        }
+        inline Container<char> make_thingy() {
+            Container<char> a('a');
+            return a;
+        }
+    "};
+    let rs = quote! {
+        let _a: cxx::UniquePtr<ffi::AutocxxConcreteContainerUint16T> = ffi::make_thingy();
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["make_thingy", "make_short_thingy"],
+        &[],
+        Some(quote! { concrete!("Container<char>", "AutocxxConcreteContainerUint16T") }),
+        &[],
+        Some(make_error_finder("make_short_thingy")),
+    );
+}
+
+#[test]
+fn test_unsafe_fn_override() {
+    let cxx = indoc! {"
+        uint32_t give_int() { return 5; }
+        uint32_t give_other_int() { return 6; }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        uint32_t give_int();
+        uint32_t give_other_int();
+    "};
+    let rs = quote! {
+        assert_eq!(unsafe { ffi::give_int() }, 5);
+        assert_eq!(ffi::give_other_int(), 6);
+    };
+    run_test_ex(
+        cxx,
+        hdr,
+        rs,
+        &["give_int", "give_other_int"],
+        &[],
+        Some(quote! { unsafe_fn!("give_int") }),
+        &[],
+        Some(Box::new(|f: syn::File| {
+            let ts = f.into_token_stream().to_string();
+            if ts.contains("unsafe fn give_int") && !ts.contains("unsafe fn give_other_int") {
+                Ok(())
+            } else {
+                Err(TestError::RsCodeExaminationFail)
+            }
+        })),
+    );
+}
+
+#[test]
+fn test_safe_override() {
+    let cxx = indoc! {"
+        uint32_t give_int() { return 5; }
+        uint32_t give_other_int() { return 6; }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        uint32_t give_int();
+        uint32_t give_other_int();
+    "};
+    let hexathorpe = Token![#](Span::call_site());
+    do_run_test_manual(
+        cxx,
+        hdr,
+        |hdr| {
+            quote! {
+                use autocxx::include_cpp;
+
+                include_cpp!(
+                    #hexathorpe include #hdr
+                    generate!("give_int")
+                    generate!("give_other_int")
+                    safe!("give_int")
+                );
+
+                fn main() {
+                    assert_eq!(ffi::give_int(), 5);
+                    assert_eq!(unsafe { ffi::give_other_int() }, 6);
+                }
+            }
+        },
+        &[],
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_define_directive() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #ifdef USE_FOO
+        inline uint32_t give_int() { return FOO_VALUE; }
+        #endif
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::give_int(), 5);
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["give_int"],
+        &[],
+        Some(quote! {
+            define!("USE_FOO")
+            define!("FOO_VALUE", "5")
+        }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_define_directives_per_block() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #ifdef USE_FOO
+        inline uint32_t give_foo() { return VALUE; }
+        #endif
+        #ifdef USE_BAR
+        inline uint32_t give_bar() { return VALUE; }
+        #endif
+    "};
+    let hexathorpe = Token![#](Span::call_site());
+    do_run_test_manual(
+        "",
+        hdr,
+        |hdr| {
+            quote! {
+                use autocxx::include_cpp;
+
+                include_cpp!(
+                    #hexathorpe include #hdr
+                    safety!(unsafe_ffi)
+                    name!(foo_ffi)
+                    generate!("give_foo")
+                    define!("USE_FOO")
+                    define!("VALUE", "1")
+                );
+
+                include_cpp!(
+                    #hexathorpe include #hdr
+                    safety!(unsafe_ffi)
+                    name!(bar_ffi)
+                    generate!("give_bar")
+                    define!("USE_BAR")
+                    define!("VALUE", "2")
+                );
+
+                fn main() {
+                    assert_eq!(foo_ffi::give_foo(), 1);
+                    assert_eq!(bar_ffi::give_bar(), 2);
+                }
+            }
+        },
+        &[],
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_cpp_std_directive() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #if __cplusplus >= 201703L
+        inline constexpr uint32_t VALUE = 17;
+        inline uint32_t give_int() { return VALUE; }
+        #endif
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::give_int(), 17);
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["give_int"],
+        &[],
+        Some(quote! { cpp_std!("c++17") }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_system_include() {
+    let cxx = indoc! {"
+        uint32_t give_int() { return 5; }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        uint32_t give_int();
+    "};
+    let hexathorpe = Token![#](Span::call_site());
+    do_run_test_manual(
+        cxx,
+        hdr,
+        |_| {
+            quote! {
+                use autocxx::include_cpp;
+
+                include_cpp!(
+                    #hexathorpe include <input.h>
+                    safety!(unsafe_ffi)
+                    generate!("give_int")
+                );
+
+                fn main() {
+                    assert_eq!(ffi::give_int(), 5);
+                }
+            }
+        },
+        &[],
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_mod_name() {
+    let cxx = indoc! {"
+        uint32_t give_int() { return 5; }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        uint32_t give_int();
+    "};
+    let hexathorpe = Token![#](Span::call_site());
+    do_run_test_manual(
+        cxx,
+        hdr,
+        |hdr| {
+            quote! {
+                use autocxx::include_cpp;
+
+                include_cpp!(
+                    #hexathorpe include #hdr
+                    safety!(unsafe_ffi)
+                    name!(net_ffi)
+                    generate!("give_int")
+                );
+
+                fn main() {
+                    assert_eq!(net_ffi::give_int(), 5);
+                }
+            }
+        },
+        &[],
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_config_file() {
+    let cxx = indoc! {"
+        uint32_t give_int() { return 5; }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        uint32_t give_int();
+    "};
+    let config_dir = tempdir().unwrap();
+    let config_path = config_dir.path().join("autocxx.toml");
+    std::fs::write(
+        &config_path,
+        indoc! {r#"
+            headers = ["input.h"]
+            generate = ["give_int"]
+            safety = "unsafe_ffi"
+        "#},
+    )
+    .unwrap();
+    let config_path = config_path.to_str().unwrap().to_string();
+    do_run_test_manual(
+        cxx,
+        hdr,
+        |_| {
+            quote! {
+                use autocxx::include_cpp;
+
+                include_cpp!(
+                    config_file!(#config_path)
+                );
+
+                fn main() {
+                    assert_eq!(ffi::give_int(), 5);
+                }
+            }
+        },
+        &[],
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_optional_pod() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <optional>
+        inline std::optional<uint32_t> give_opt(bool b) {
+            if (b) {
+                return 5;
+            }
+            return std::nullopt;
+        }
+        inline uint32_t take_opt(std::optional<uint32_t> a) { return a.value_or(3); }
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::give_opt(true), Some(5));
+        assert_eq!(ffi::give_opt(false), None);
+        assert_eq!(ffi::take_opt(Some(4)), 4);
+        assert_eq!(ffi::take_opt(None), 3);
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["give_opt", "take_opt"],
+        &[],
+        None,
+        &["-std=c++17"],
+        None,
+    );
+}
+
+#[test]
+fn test_optional_nonpod() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <optional>
+        #include <string>
+        struct A {
+            std::string a;
+        };
+        inline std::optional<A> give_opt(bool b) {
+            if (b) {
+                return A { \"hello\" };
+            }
+            return std::nullopt;
+        }
+        inline uint32_t take_opt(const std::optional<A>& a) {
+            return a ? a->a.size() : 0;
+        }
+    "};
+    let rs = quote! {
+        let a = ffi::give_opt(true);
+        assert!(a.is_some());
+        assert!(ffi::give_opt(false).is_none());
+        assert_eq!(ffi::take_opt(a), 5);
+        assert_eq!(ffi::take_opt(None), 0);
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["A", "give_opt", "take_opt"],
+        &[],
+        None,
+        &["-std=c++17"],
+        None,
+    );
+}
+
+#[test]
+fn test_optional_mut_ref_rejected() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <optional>
+        inline void fill_opt(std::optional<uint32_t>& a) { a = 4; }
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["fill_opt"],
+        &[],
+        None,
+        &["-std=c++17"],
+        Some(make_error_finder("fill_opt")),
+    );
+}
+
+#[test]
+fn test_slice_directive() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <cstddef>
+        inline uint32_t sum(const uint8_t* data, size_t len) {
+            uint32_t total = 0;
+            for (size_t i = 0; i < len; i++) {
+                total += data[i];
+            }
+            return total;
+        }
+        inline void fill(uint8_t* data, size_t len, uint8_t value) {
+            for (size_t i = 0; i < len; i++) {
+                data[i] = value;
+            }
+        }
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::sum(&[1, 2, 3]), 6);
+        let mut buf = [0u8; 4];
+        ffi::fill(&mut buf, 7);
+        assert_eq!(buf, [7u8; 4]);
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["sum", "fill"],
+        &[],
+        Some(quote! {
+            slice!("sum", "data", "len")
+            slice!("fill", "data", "len")
+        }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_span() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <span>
+        inline uint32_t sum_span(std::span<const uint8_t> data) {
+            uint32_t total = 0;
+            for (auto i : data) {
+                total += i;
+            }
+            return total;
+        }
+        inline void double_span(std::span<int32_t> data) {
+            for (auto& i : data) {
+                i *= 2;
+            }
+        }
+        inline void copy_span(std::span<const int32_t> from, std::span<int32_t> to) {
+            for (size_t i = 0; i < from.size() && i < to.size(); i++) {
+                to[i] = from[i];
+            }
+        }
+    "};
+    let rs = quote! {
+        let a = [1u8, 2, 3];
+        assert_eq!(ffi::sum_span(&a), 6);
+        let mut b = [1i32, 2];
+        ffi::double_span(&mut b);
+        assert_eq!(b, [2, 4]);
+        let c = [5i32, 6];
+        ffi::copy_span(&c, &mut b);
+        assert_eq!(b, [5, 6]);
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["sum_span", "double_span", "copy_span"],
+        &[],
+        None,
+        &["-std=c++20"],
+        None,
+    );
+}
+
+#[test]
+fn test_std_function_from_closure() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <functional>
+        inline uint32_t call_twice(std::function<uint32_t(uint32_t)> f) {
+            return f(1) + f(2);
+        }
+        inline uint32_t call_copy(const std::function<uint32_t(uint32_t)>& f) {
+            auto g = f;
+            return g(3);
+        }
+    "};
+    let rs = quote! {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        assert_eq!(ffi::call_twice(|x| x * 10), 30);
+        let calls = std::sync::Arc::new(AtomicU32::new(0));
+        let calls_clone = calls.clone();
+        assert_eq!(ffi::call_copy(move |x| x + calls_clone.fetch_add(1, Ordering::SeqCst)), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    };
+    run_test("", hdr, rs, &["call_twice", "call_copy"], &[]);
+}
+
+#[test]
+fn test_function_pointer_with_user_data() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        typedef void (*listener_t)(void* user_data, uint32_t event);
+        inline void notify(listener_t listener, void* user_data) {
+            listener(user_data, 1);
+            listener(user_data, 2);
+        }
+        inline uint32_t apply(uint32_t (*f)(uint32_t, void*), void* context, uint32_t value) {
+            return f(value, context);
+        }
+        inline uint32_t apply_const(uint32_t (*f)(const void*, uint32_t), const void* context,
+                                    uint32_t value) {
+            return f(context, value);
+        }
+    "};
+    let rs = quote! {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events_clone = events.clone();
+        let _handle =
+            unsafe { ffi::notify(move |event| events_clone.lock().unwrap().push(event)) };
+        assert_eq!(*events.lock().unwrap(), vec![1, 2]);
+        assert_eq!(unsafe { ffi::apply(|x| x + 1, 4) }.0, 5);
+        assert_eq!(unsafe { ffi::apply_const(|x| x + 2, 4) }.0, 6);
+    };
+    run_test("", hdr, rs, &["notify", "apply", "apply_const"], &[]);
+}
+
+#[test]
+fn test_function_pointer_callback_handle() {
+    let cxx = indoc! {"
+        static listener_t registered_listener = nullptr;
+        static void* registered_user_data = nullptr;
+        void add_listener(listener_t listener, void* user_data) {
+            registered_listener = listener;
+            registered_user_data = user_data;
+        }
+        void remove_listener() {
+            registered_listener = nullptr;
+        }
+        void fire(uint32_t event) {
+            if (registered_listener) {
+                registered_listener(registered_user_data, event);
+            }
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        typedef void (*listener_t)(void* user_data, uint32_t event);
+        void add_listener(listener_t listener, void* user_data);
+        void remove_listener();
+        void fire(uint32_t event);
+    "};
+    let rs = quote! {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events_clone = events.clone();
+        let handle =
+            unsafe { ffi::add_listener(move |event| events_clone.lock().unwrap().push(event)) };
+        ffi::fire(1);
+        ffi::fire(2);
+        ffi::remove_listener();
+        ffi::fire(3);
+        assert_eq!(*events.lock().unwrap(), vec![1, 2]);
+        // Dropping the handle frees the closure, and with it our clone.
+        assert_eq!(std::sync::Arc::strong_count(&events), 2);
+        drop(handle);
+        assert_eq!(std::sync::Arc::strong_count(&events), 1);
+    };
+    run_test(
+        cxx,
+        hdr,
+        rs,
+        &["add_listener", "remove_listener", "fire"],
+        &[],
+    );
+}
+
+#[test]
+fn test_function_pointer_without_user_data() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        inline uint32_t apply(uint32_t (*f)(uint32_t), uint32_t value) {
+            return f(value);
+        }
+    "};
+    let rs = quote! {
+        extern "C" fn add_one(x: u32) -> u32 {
+            x + 1
+        }
+        assert_eq!(ffi::apply(add_one, 4), 5);
+    };
+    run_test("", hdr, rs, &["apply"], &[]);
+}
+
+#[test]
+fn test_make_shared() {
+    let cxx = indoc! {"
+        Bob::Bob() : a(3) {}
+        Bob::Bob(uint32_t _a) : a(_a) {}
+        uint32_t Bob::get() const { return a; }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <memory>
+        #include <string>
+        struct Bob {
+            Bob();
+            Bob(uint32_t a);
+            uint32_t get() const;
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+    "};
+    let rs = quote! {
+        let a = ffi::Bob::make_shared();
+        assert_eq!(a.get(), 3);
+        let b = ffi::Bob::make_shared1(32);
+        let c = b.clone();
+        assert_eq!(c.get(), 32);
+        assert_eq!(ffi::Bob::make_unique1(12).get(), 12);
+    };
+    run_test(cxx, hdr, rs, &["Bob"], &[]);
+}
+
+#[test]
+fn test_make_shared_with_callback() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <functional>
+        #include <memory>
+        #include <string>
+        struct Scaled {
+            Scaled(std::function<uint32_t(uint32_t)> f) : a(f(4)) {}
+            uint32_t get() const { return a; }
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+    "};
+    let rs = quote! {
+        let a = ffi::Scaled::make_shared(|x| x * 2);
+        assert_eq!(a.get(), 8);
+        let b = ffi::Scaled::make_unique(|x| x + 1);
+        assert_eq!(b.get(), 5);
+    };
+    run_test("", hdr, rs, &["Scaled"], &[]);
+}
+
+#[test]
+fn test_shared_ptr_params() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <memory>
+        #include <string>
+        struct Bob {
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+        inline std::shared_ptr<Bob> make_bob(uint32_t a) {
+            auto b = std::make_shared<Bob>();
+            b->a = a;
+            return b;
+        }
+        inline uint32_t take_bob(std::shared_ptr<Bob> b) {
+            return b->a;
+        }
+        inline uint32_t take_bob_ref(const std::shared_ptr<Bob>& b) {
+            return b->a + 1;
+        }
+        inline uint32_t use_count(const std::shared_ptr<Bob>& b) {
+            return b.use_count();
+        }
+    "};
+    let rs = quote! {
+        let b = ffi::make_bob(12);
+        assert_eq!(ffi::take_bob(b.clone()), 12);
+        assert_eq!(ffi::take_bob_ref(&b), 13);
+        let c = b.clone();
+        assert_eq!(ffi::use_count(&c), 2);
+    };
+    run_test(
+        "",
+        hdr,
+        rs,
+        &["make_bob", "take_bob", "take_bob_ref", "use_count"],
+        &[],
+    );
+}
+
+#[test]
+fn test_shared_ptr_impls_only_where_needed() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <memory>
+        #include <string>
+        struct Bob {
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+        struct Fred {
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+        inline std::shared_ptr<Bob> make_bob() {
+            return std::make_shared<Bob>();
+        }
+        inline std::unique_ptr<Fred> make_fred() {
+            return std::make_unique<Fred>();
+        }
+    "};
+    let rs = quote! {
+        assert!(!ffi::make_bob().is_null());
+        assert!(!ffi::make_fred().is_null());
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["make_bob", "make_fred"],
+        &[],
+        None,
+        &[],
+        Some(Box::new(|f: syn::File| {
+            let ts = f.into_token_stream().to_string();
+            if ts.contains("impl SharedPtr < Bob >")
+                && ts.contains("impl WeakPtr < Bob >")
+                && !ts.contains("impl SharedPtr < Fred >")
+                && !ts.contains("impl WeakPtr < Fred >")
+            {
+                Ok(())
+            } else {
+                Err(TestError::RsCodeExaminationFail)
+            }
+        })),
+    );
+}
+
+#[test]
+fn test_weak_ptr() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <memory>
+        #include <string>
+        struct Bob {
+            uint32_t get() const { return a; }
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+        inline std::shared_ptr<Bob> make_bob(uint32_t a) {
+            auto b = std::make_shared<Bob>();
+            b->a = a;
+            return b;
+        }
+        inline std::weak_ptr<Bob> observe(const std::shared_ptr<Bob>& b) {
+            return b;
+        }
+        inline bool is_expired(std::weak_ptr<Bob> b) {
+            return b.expired();
+        }
+    "};
+    let rs = quote! {
+        let b = ffi::make_bob(12);
+        let w = ffi::observe(&b);
+        assert!(!ffi::is_expired(w.clone()));
+        assert_eq!(w.upgrade().as_ref().unwrap().get(), 12);
+        drop(b);
+        assert!(w.upgrade().is_null());
+        assert!(ffi::is_expired(w));
+    };
+    run_test(
+        "",
+        hdr,
+        rs,
+        &["make_bob", "observe", "is_expired", "Bob"],
+        &[],
+    );
+}
+
+#[test]
+fn test_weak_ptr_round_trip() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <memory>
+        #include <string>
+        struct Bob {
+            uint32_t get() const { return a; }
+            uint32_t a;
+            std::string reason_why_this_is_nonpod;
+        };
+        inline std::shared_ptr<Bob> make_bob(uint32_t a) {
+            auto b = std::make_shared<Bob>();
+            b->a = a;
+            return b;
+        }
+        inline uint32_t get_if_alive(std::weak_ptr<Bob> w) {
+            if (auto b = w.lock()) {
+                return b->a;
+            }
+            return 0;
+        }
+        inline std::weak_ptr<Bob> pass_through(const std::weak_ptr<Bob>& w) {
+            return w;
+        }
+    "};
+    let rs = quote! {
+        let b = ffi::make_bob(12);
+        // Downgraded in Rust, upgraded in C++.
+        let w = b.downgrade();
+        assert_eq!(ffi::get_if_alive(w.clone()), 12);
+        // Passed through C++ and upgraded in Rust.
+        let w2 = ffi::pass_through(&w);
+        assert_eq!(w2.upgrade().as_ref().unwrap().get(), 12);
+        drop(b);
+        assert_eq!(ffi::get_if_alive(w), 0);
+        assert!(w2.upgrade().is_null());
+    };
+    run_test(
+        "",
+        hdr,
+        rs,
+        &["make_bob", "get_if_alive", "pass_through", "Bob"],
+        &[],
+    );
+}
+
+#[test]
+fn test_map() {
+    let cxx = indoc! {"
+        std::map<uint32_t, std::string> make_map() {
+            std::map<uint32_t, std::string> m;
+            m[2] = \"two\";
+            m[1] = \"one\";
+            return m;
+        }
+        uint32_t map_size(const std::map<uint32_t, std::string>& m) {
+            return m.size();
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <map>
+        #include <string>
+        std::map<uint32_t, std::string> make_map();
+        uint32_t map_size(const std::map<uint32_t, std::string>& m);
+    "};
+    let rs = quote! {
+        let mut m = ffi::make_map();
+        assert_eq!(m.len(), 2);
+        assert!(m.contains_key(&1));
+        assert_eq!(m.get(&1).unwrap().to_str().unwrap(), "one");
+        assert!(m.get(&3).is_none());
+        let keys: Vec<u32> = m.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![1, 2]);
+        let rust_map = m.to_btree_map();
+        assert_eq!(rust_map[&2], "two");
+        cxx::let_cxx_string!(three = "three");
+        m.pin_mut().insert(&3, &three);
+        assert_eq!(ffi::map_size(&m), 3);
+    };
+    run_test(cxx, hdr, rs, &["make_map", "map_size"], &[]);
+}
+
+#[test]
+fn test_unordered_map_from_hash_map() {
+    let cxx = indoc! {"
+        int total(const std::unordered_map<std::string, int>& m) {
+            int total = 0;
+            for (const auto& entry : m) {
+                total += entry.second;
+            }
+            return total;
+        }
+    "};
+    let hdr = indoc! {"
+        #include <string>
+        #include <unordered_map>
+        int total(const std::unordered_map<std::string, int>& m);
+    "};
+    let rs = quote! {
+        let mut rust_map = std::collections::HashMap::new();
+        rust_map.insert("a".to_string(), autocxx::c_int(3));
+        rust_map.insert("b".to_string(), autocxx::c_int(4));
+        let m = ffi::StringToIntMap::from_hash_map(&rust_map);
+        assert_eq!(ffi::total(&m), autocxx::c_int(7));
+        assert_eq!(m.to_hash_map(), rust_map);
+    };
+    run_test_ex(
+        cxx,
+        hdr,
+        rs,
+        &["total"],
+        &[],
+        Some(quote! { concrete!("std::unordered_map<std::string, int>", "StringToIntMap") }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_map_of_pod_values() {
+    let cxx = indoc! {"
+        std::map<uint32_t, Point> make_points() {
+            std::map<uint32_t, Point> m;
+            m[1] = Point { 2, 3 };
+            return m;
+        }
+        uint32_t sum_points(const std::map<uint32_t, Point>& m) {
+            uint32_t total = 0;
+            for (const auto& entry : m) {
+                total += entry.second.x + entry.second.y;
+            }
+            return total;
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <map>
+        struct Point {
+            uint32_t x;
+            uint32_t y;
+        };
+        std::map<uint32_t, Point> make_points();
+        uint32_t sum_points(const std::map<uint32_t, Point>& m);
+    "};
+    let rs = quote! {
+        let m = ffi::make_points();
+        assert_eq!(m.get(&1).unwrap().y, 3);
+        assert!(m.get(&2).is_none());
+        let mut rust_map = m.to_btree_map();
+        assert_eq!(rust_map[&1].x, 2);
+        rust_map.insert(4, ffi::Point { x: 5, y: 6 });
+        let m = ffi::PointMap::from_btree_map(&rust_map);
+        assert_eq!(ffi::sum_points(&m), 16);
+    };
+    run_test_ex(
+        cxx,
+        hdr,
+        rs,
+        &["make_points", "sum_points"],
+        &["Point"],
+        Some(quote! { concrete!("std::map<uint32_t, Point>", "PointMap") }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_return_pair() {
+    let cxx = indoc! {"
+        std::pair<uint32_t, bool> get_pair() {
+            return std::make_pair(3, true);
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <utility>
+        std::pair<uint32_t, bool> get_pair();
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::get_pair(), (3, true));
+    };
+    run_test(cxx, hdr, rs, &["get_pair"], &[]);
+}
+
+#[test]
+fn test_return_pair_with_string_and_pod() {
+    let cxx = indoc! {"
+        std::pair<std::string, Point> get_named_point() {
+            return std::make_pair(std::string(\"origin\"), Point { 1, 2 });
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <string>
+        #include <utility>
+        struct Point {
+            uint32_t x;
+            uint32_t y;
+        };
+        std::pair<std::string, Point> get_named_point();
+    "};
+    let rs = quote! {
+        let (name, point) = ffi::get_named_point();
+        assert_eq!(name.to_str().unwrap(), "origin");
+        assert_eq!(point.y, 2);
+    };
+    run_test(cxx, hdr, rs, &["get_named_point"], &["Point"]);
+}
+
+#[test]
+fn test_return_tuple_rejected() {
+    // bindgen doesn't give us the type arguments of variadic templates,
+    // so we can't yet do anything with std::tuple.
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <tuple>
+        inline std::tuple<uint32_t, bool, uint8_t> get_tuple() {
+            return std::make_tuple(1, true, 3);
+        }
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["get_tuple"],
+        &[],
+        None,
+        &[],
+        Some(make_error_finder("get_tuple")),
+    );
+}
+
+#[test]
+fn test_pair_getters() {
+    let cxx = indoc! {"
+        std::pair<Counter, uint32_t> make_counter_pair() {
+            return std::make_pair(Counter(), 7);
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <utility>
+        class Counter {
+        public:
+            Counter() : count(5) {}
+            ~Counter() {}
+            uint32_t get_count() const { return count; }
+        private:
+            uint32_t count;
+        };
+        std::pair<Counter, uint32_t> make_counter_pair();
+    "};
+    let rs = quote! {
+        let p = ffi::make_counter_pair();
+        assert_eq!(p.first().get_count(), 5);
+        assert_eq!(*p.second(), 7);
+    };
+    run_test(cxx, hdr, rs, &["make_counter_pair", "Counter"], &[]);
+}
+
+#[test]
+fn test_variant_rejected() {
+    // Until bindgen gives us the type arguments of variadic templates,
+    // functions using std::variant are skipped.
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <variant>
+        inline uint32_t variant_index(const std::variant<uint32_t, bool>& v) {
+            return v.index();
+        }
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["variant_index"],
+        &[],
+        None,
+        &["-std=c++17"],
+        Some(make_error_finder("variant_index")),
+    );
+}
+
+#[test]
+fn test_u16string() {
+    let cxx = indoc! {"
+        std::u16string greet(const std::u16string& name) {
+            return u\"Hello, \" + name;
+        }
+        size_t count_units(const std::u16string& s) {
+            return s.size();
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstddef>
+        #include <string>
+        std::u16string greet(const std::u16string& name);
+        size_t count_units(const std::u16string& s);
+    "};
+    let rs = quote! {
+        let name = ffi::U16String::new_from_str("w\u{f6}rld \u{1f600}");
+        assert_eq!(ffi::count_units(&name), 8);
+        let greeting = ffi::greet(&name);
+        assert_eq!(greeting.to_string_lossy(), "Hello, w\u{f6}rld \u{1f600}");
+        assert_eq!(greeting.to_units()[..7], "Hello, ".encode_utf16().collect::<Vec<u16>>()[..]);
+        let units = ffi::U16String::new_from_units(&[0x48, 0x69]);
+        assert_eq!(units.to_string_lossy(), "Hi");
+    };
+    run_test_ex(
+        cxx,
+        hdr,
+        rs,
+        &["greet", "count_units"],
+        &[],
+        Some(quote! { concrete!("std::u16string", "U16String") }),
+        &[],
+        None,
+    );
+}
+
+#[test]
+fn test_wstring() {
+    let cxx = indoc! {"
+        std::wstring shout(const std::wstring& s) {
+            return s + L\"!\";
+        }
+    "};
+    let hdr = indoc! {"
+        #include <string>
+        std::wstring shout(const std::wstring& s);
+    "};
+    let rs = quote! {
+        let s = ffi::AutocxxConcreteStdWstring::new_from_str("h\u{e9}llo");
+        assert_eq!(ffi::shout(&s).to_string_lossy(), "h\u{e9}llo!");
+    };
+    run_test(cxx, hdr, rs, &["shout"], &[]);
+}
+
+#[test]
+fn test_user_type_named_u16string() {
+    // Wide string types are only generated when used, and have
+    // autocxx-specific names, so they don't clash with user types.
+    let hdr = indoc! {"
+        #include <cstdint>
+        struct U16String {
+            uint32_t length;
+        };
+        inline uint32_t get_length(U16String s) {
+            return s.length;
+        }
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::get_length(ffi::U16String { length: 3 }), 3);
+    };
+    run_test("", hdr, rs, &["get_length"], &["U16String"]);
+}
+
+#[test]
+fn test_wide_char_fields() {
+    let cxx = indoc! {"
+        Glyphs make_glyphs() {
+            Glyphs g;
+            g.utf16 = u'A';
+            g.utf32 = U'\\U0001F600';
+            g.wide = L'Z';
+            return g;
+        }
+    "};
+    let hdr = indoc! {"
+        struct Glyphs {
+            char16_t utf16;
+            char32_t utf32;
+            wchar_t wide;
+        };
+        Glyphs make_glyphs();
+    "};
+    let rs = quote! {
+        let g = ffi::make_glyphs();
+        let utf16: u16 = g.utf16;
+        let utf32: u32 = g.utf32;
+        let wide: u32 = g.wide.into();
+        assert_eq!(utf16, 0x41);
+        assert_eq!(utf32, 0x1f600);
+        assert_eq!(wide, 0x5a);
+    };
+    run_test(cxx, hdr, rs, &["make_glyphs"], &["Glyphs"]);
+}
+
+#[test]
+fn test_pod_with_bitfields() {
+    let cxx = indoc! {"
+        Flags make_flags() {
+            Flags f;
+            f.a = 5;
+            f.b = 17;
+            f.c = true;
+            f.d = 100;
+            return f;
+        }
+        uint32_t sum_flags(Flags f) {
+            return f.a + f.b + f.c + f.d;
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        struct Flags {
+            unsigned int a : 3;
+            unsigned int b : 5;
+            bool c : 1;
+            uint16_t d;
+        };
+        Flags make_flags();
+        uint32_t sum_flags(Flags f);
+    "};
+    let rs = quote! {
+        let mut f = ffi::make_flags();
+        assert_eq!(f.a(), 5);
+        assert_eq!(f.b(), 17);
+        assert!(f.c());
+        assert_eq!(f.d, 100);
+        f.set_a(2);
+        f.set_b(30);
+        f.set_c(false);
+        assert_eq!(ffi::sum_flags(f), 132);
+    };
+    run_test(cxx, hdr, rs, &["make_flags", "sum_flags"], &["Flags"]);
+}
+
+#[test]
+fn test_pod_with_bitfields_in_namespace() {
+    let cxx = indoc! {"
+        hw::Reg make_reg() {
+            hw::Reg r;
+            r.mode = 3;
+            r.enabled = true;
+            r.count = 9;
+            return r;
+        }
+    "};
+    let hdr = indoc! {"
+        #include <cstdint>
+        namespace hw {
+            struct Reg {
+                unsigned int mode : 2;
+                bool enabled : 1;
+                uint32_t count;
+            };
+        }
+        hw::Reg make_reg();
+    "};
+    let rs = quote! {
+        let mut r = ffi::make_reg();
+        assert_eq!(r.mode(), 3);
+        assert!(r.enabled());
+        assert_eq!(r.count, 9);
+        r.set_mode(1);
+        r.set_enabled(false);
+        assert_eq!(r.mode(), 1);
+        assert!(!r.enabled());
+    };
+    run_test(cxx, hdr, rs, &["make_reg"], &["hw::Reg"]);
+}
+
+#[test]
+fn test_non_pod_with_bitfields() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <string>
+        struct Register {
+            Register() : enabled(1), mode(2) {}
+            uint32_t get_mode() const { return mode; }
+            uint32_t enabled : 1;
+            uint32_t mode : 4;
+            std::string name;
+        };
+    "};
+    let rs = quote! {
+        let r = ffi::Register::make_unique();
+        assert_eq!(r.get_mode(), 2);
+    };
+    run_test("", hdr, rs, &["Register"], &[]);
+}
+
+fn find_ffi_items(f: syn::File) -> Result<Vec<Item>, TestError> {
+    Ok(f.items
+        .into_iter()
+        .filter_map(|i| match i {
+            Item::Mod(itm) => Some(itm),
+            _ => None,
+        })
+        .next()
+        .ok_or(TestError::RsCodeExaminationFail)?
+        .content
+        .ok_or(TestError::RsCodeExaminationFail)?
+        .1)
+}
+
+/// Generates a closure which can be used to ensure that the given symbol
+/// is mentioned in the output and has documentation attached.
+/// The idea is that this is what we do in cases where we can't generate code properly.
+fn make_error_finder(error_symbol: &str) -> Box<dyn FnOnce(syn::File) -> Result<(), TestError>> {
+    let error_symbol = error_symbol.to_string();
+    Box::new(move |f| {
+        let ffi_items = find_ffi_items(f)?;
+        // Ensure there's some kind of struct entry for this symboll
+        let foo = ffi_items
+            .into_iter()
+            .filter_map(|i| match i {
+                Item::Struct(its) if its.ident.to_string() == error_symbol => Some(its),
+                _ => None,
+            })
+            .next()
+            .ok_or(TestError::RsCodeExaminationFail)?;
+        // Ensure doc attribute
+        foo.attrs
+            .into_iter()
+            .filter(|a| {
+                a.path
+                    .get_ident()
+                    .filter(|p| p.to_string() == "doc")
+                    .is_some()
+            })
+            .next()
+            .ok_or(TestError::RsCodeExaminationFail)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_error_generated_for_static_data() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        struct A {
+            A() {}
+            uint32_t a;
+        };
+        static A FOO = A();
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["FOO"],
+        &[],
+        None,
+        &[],
+        Some(make_error_finder("FOO")),
+    );
+}
+
+#[test]
+fn test_error_generated_for_array_dependent_function() {
+    // Callbacks may only take POD types by value.
+    let hdr = indoc! {"
+        #include <functional>
+        #include <string>
+        inline void take_func(std::function<bool(const std::string& name)>) {
+        }
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["take_func"],
+        &[],
+        None,
+        &[],
+        Some(make_error_finder("take_func")),
+    );
+}
+
+#[test]
+fn test_error_generated_for_array_dependent_method() {
+    let hdr = indoc! {"
+        #include <functional>
+        #include <string>
+        struct A {
+            void take_func(std::function<bool(const std::string& name)>) {
+            }
+        };
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["A"],
+        &[],
+        None,
+        &[],
+        Some(make_string_finder(
+            ["take_func", "couldn't be generated"].to_vec(),
+        )),
+    );
+}
+
+#[test]
+fn test_std_function_with_const_param() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <functional>
+        inline bool take_func(std::function<bool(const uint32_t number)> f) {
+            return f(4);
+        }
+    "};
+    let rs = quote! {
+        assert!(ffi::take_func(|number| number == 4));
+    };
+    run_test("", hdr, rs, &["take_func"], &[]);
+}
+
+#[test]
+fn test_std_function_with_const_param_method() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <functional>
+        struct A {
+            uint32_t a;
+            bool take_func(std::function<bool(const uint32_t number)> f) const {
+                return f(a);
+            }
+        };
+    "};
+    let rs = quote! {
+        let a = ffi::A { a: 4 };
+        assert!(a.take_func(|number| number == 4));
+    };
+    run_test("", hdr, rs, &[], &["A"]);
+}
+
+/// Returns a closure which simply hunts for a given string in the results
+fn make_string_finder<'a>(
+    error_texts: Vec<&str>,
+) -> Box<dyn FnOnce(syn::File) -> Result<(), TestError> + '_> {
+    Box::new(|f| {
+        let mut ts = TokenStream::new();
+        f.to_tokens(&mut ts);
+        let toks = ts.to_string();
+        for msg in error_texts {
+            if !toks.contains(msg) {
+                return Err(TestError::RsCodeExaminationFail);
+            };
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_doc_passthru() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        /// Elephants!
+        struct A {
+            uint32_t a;
+        };
+        /// Giraffes!
+        struct B {
+            uint32_t a;
+        };
+        /// Rhinos!
+        inline uint32_t get_a() { return 3; }
+    "};
+    let rs = quote! {};
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["A", "get_a"],
+        &["B"],
+        None,
+        &[],
+        Some(make_string_finder(
+            ["Giraffes", "Elephants", "Rhinos"].to_vec(),
+        )),
+    );
+}
+
+#[test]
+fn test_closure() {
+    // Ensuring presence of this closure doesn't break other things
+    let hdr = indoc! {"
+    #include <functional>
+    #include <cstdint>
+
+    inline bool take_closure(std::function<bool(const uint32_t number)> fn) {
+        return fn(5);
+    }
+    inline uint32_t get_a() {
+        return 3;
     }
     "};
     let rs = quote! {
@@ -4861,30 +6611,81 @@ fn test_blocklist_not_overly_broad() {
     run_test("", hdr, rs, &["rust_func", "std_func"], &[]);
 }
 
+#[test]
+fn test_stringview() {
+    let hdr = indoc! {"
+        #include <cstdint>
+        #include <string_view>
+        #include <string>
+        inline uint32_t take_string_view(std::string_view a) { return a.size(); }
+        inline std::string_view return_string_view(std::string_view a) { return a.substr(1); }
+        class A {
+        public:
+            A() : name(\"hello\") {}
+            std::string_view get_name() const { return name; }
+        private:
+            std::string name;
+        };
+    "};
+    let rs = quote! {
+        assert_eq!(ffi::take_string_view("hello"), 5);
+        assert_eq!(ffi::return_string_view("hello"), Ok("ello"));
+        let a = ffi::A::make_unique();
+        assert_eq!(a.get_name(), Ok("hello"));
+    };
+    run_test_ex(
+        "",
+        hdr,
+        rs,
+        &["take_string_view", "return_string_view", "A"],
+        &[],
+        None,
+        &["-std=c++17"],
+        None,
+    );
+}
+
+#[test]
+fn test_stringview_not_utf8() {
+    let hdr = indoc! {"
+        #include <string_view>
+        #include <string>
+        class Blob {
+        public:
+            Blob() : data(\"\\xff\\xfe\") {}
+            std::string_view get_data() const { return data; }
+        private:
+            std::string data;
+        };
+    "};
+    let rs = quote! {
+        let blob = ffi::Blob::make_unique();
+        assert!(blob.get_data().is_err());
+    };
+    run_test_ex("", hdr, rs, &["Blob"], &[], None, &["-std=c++17"], None);
+}
+
 #[test]
 fn test_stringview_ignored() {
-    // Test that APIs using std::string_view are ignored but do not otherwise cause errors.
+    // Test that APIs using std::string_view other than by value or const reference
+    // are ignored but do not otherwise cause errors.
     // This is a regression test: We used to blocklist std::string_view but still import APIs that
     // use it, which caused cxx to complain that it didn't know about the type.
-    // Once we actually support std::string_view, this test can be extended to actually call
-    // take_string_view().
     let hdr = indoc! {"
         #include <string_view>
-        #include <string>
-        void take_string_view(std::string_view) {}
-        std::string_view return_string_view(std::string a) { return std::string_view(a); }
+        inline void take_string_view_ref(std::string_view&) {}
     "};
     let rs = quote! {};
     run_test_ex(
         "",
         hdr,
         rs,
-        &["take_string_view", "return_string_view"],
+        &["take_string_view_ref"],
         &[],
         None,
         &["-std=c++17"],
         Some(make_string_finder(
-            ["take_string_view", "return_string_view", "std::string_view"].to_vec(),
+            ["take_string_view_ref", "StringView"].to_vec(),
         )),
     );
 }
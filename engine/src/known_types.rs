@@ -27,6 +27,15 @@ enum Behavior {
     CxxContainerByValueSafe,
     CxxContainerNotByValueSafe,
     CxxString,
+    CxxOptional,
+    CxxStringView,
+    CxxSpan,
+    CxxFunction,
+    CxxMap,
+    CxxPair,
+    CxxTuple,
+    CxxVariant,
+    CxxWideString,
     RustStr,
     RustString,
     RustByValue,
@@ -69,13 +78,31 @@ impl TypeDetails {
             Behavior::RustString
             | Behavior::RustStr
             | Behavior::CxxString
+            | Behavior::CxxStringView
             | Behavior::CxxContainerByValueSafe
-            | Behavior::CxxContainerNotByValueSafe => {
+            | Behavior::CxxContainerNotByValueSafe
+            | Behavior::CxxOptional
+            | Behavior::CxxSpan
+            | Behavior::CxxFunction
+            | Behavior::CxxMap
+            | Behavior::CxxPair
+            | Behavior::CxxTuple
+            | Behavior::CxxVariant
+            | Behavior::CxxWideString => {
                 let tn = QualifiedName::new_from_user_input(&self.rs_name);
                 let cxx_name = tn.get_final_item();
                 let (templating, payload) = match self.behavior {
-                    Behavior::CxxContainerByValueSafe | Behavior::CxxContainerNotByValueSafe => {
-                        ("template<typename T> ", "T* ptr")
+                    Behavior::CxxContainerByValueSafe
+                    | Behavior::CxxContainerNotByValueSafe
+                    | Behavior::CxxOptional
+                    | Behavior::CxxSpan
+                    | Behavior::CxxFunction => ("template<typename T> ", "T* ptr"),
+                    Behavior::CxxMap => ("template<typename K, typename V> ", "K* key; V* value"),
+                    Behavior::CxxPair => {
+                        ("template<typename A, typename B> ", "A* first; B* second")
+                    }
+                    Behavior::CxxTuple | Behavior::CxxVariant => {
+                        ("template<typename... T> ", "char* ptr")
                     }
                     _ => ("", "char* ptr"),
                 };
@@ -163,6 +190,15 @@ impl TypeDatabase {
                     | Behavior::CVariableLengthByValue => true,
                     Behavior::CxxString
                     | Behavior::CxxContainerNotByValueSafe
+                    | Behavior::CxxOptional
+                    | Behavior::CxxStringView
+                    | Behavior::CxxSpan
+                    | Behavior::CxxFunction
+                    | Behavior::CxxMap
+                    | Behavior::CxxPair
+                    | Behavior::CxxTuple
+                    | Behavior::CxxVariant
+                    | Behavior::CxxWideString
                     | Behavior::CVoid => false,
                 },
             )
@@ -230,6 +266,27 @@ impl TypeDatabase {
             .unwrap_or(false)
     }
 
+    /// Whether this is a primitive type such as an integer, including the
+    /// variable-length C types.
+    pub(crate) fn is_primitive(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|td| {
+                matches!(
+                    td.behavior,
+                    Behavior::CByValue | Behavior::CVariableLengthByValue
+                )
+            })
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::unique_ptr`. cxx can't hold primitives in
+    /// a `UniquePtr`, so we wrap them in a newtype of our own.
+    pub(crate) fn is_unique_ptr(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|td| td.cpp_name == "std::unique_ptr")
+            .unwrap_or(false)
+    }
+
     /// Whether this is a generic type acceptable to cxx. Otherwise,
     /// if we encounter a generic, we'll replace it with a synthesized concrete
     /// type.
@@ -244,6 +301,85 @@ impl TypeDatabase {
             .unwrap_or(false)
     }
 
+    /// Whether this is a `std::optional`, which we represent as a Rust
+    /// `Option`. cxx can't cope with these, so they never reach the
+    /// cxx::bridge; instead wrapper functions unpack them.
+    pub(crate) fn is_optional(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxOptional))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::string_view`. cxx can't cope with these
+    /// either, so wrapper functions convert them to and from `rust::Str`.
+    pub(crate) fn is_string_view(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxStringView))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::span`, which wrapper functions create
+    /// from a `rust::Slice`.
+    pub(crate) fn is_span(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxSpan))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::function`, which wrapper functions create
+    /// from a boxed Rust closure.
+    pub(crate) fn is_function(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxFunction))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::map` or `std::unordered_map`. These become
+    /// concrete opaque types, for which we generate helper functions.
+    pub(crate) fn is_map(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxMap))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::u16string` or `std::wstring`. These become
+    /// concrete opaque types, for which we generate helper functions.
+    pub(crate) fn is_wide_string(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxWideString))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::pair`. These become concrete opaque types
+    /// with getters, though functions may return them as Rust tuples.
+    pub(crate) fn is_pair(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxPair))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::tuple`. bindgen doesn't tell us the
+    /// element types, so we can't yet do anything with these.
+    pub(crate) fn is_tuple(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxTuple))
+            .unwrap_or(false)
+    }
+
+    /// Whether this is a `std::variant`. bindgen doesn't tell us the
+    /// alternatives, so we can't yet do anything with these.
+    pub(crate) fn is_variant(&self, ty: &QualifiedName) -> bool {
+        self.get(ty)
+            .map(|x| matches!(x.behavior, Behavior::CxxVariant))
+            .unwrap_or(false)
+    }
+
+    /// Whether this type has no equivalent in cxx, such that we can only
+    /// handle it where our wrapper functions convert it.
+    pub(crate) fn lacks_cxx_equivalent(&self, ty: &QualifiedName) -> bool {
+        self.is_optional(ty) || self.is_string_view(ty) || self.is_span(ty) || self.is_function(ty)
+    }
+
     pub(crate) fn convertible_from_strs(&self, ty: &QualifiedName) -> bool {
         self.get(ty)
             .map(|x| matches!(x.behavior, Behavior::CxxString))
@@ -286,12 +422,84 @@ fn create_type_database() -> TypeDatabase {
         Behavior::CxxContainerByValueSafe,
         None,
     ));
+    db.insert(TypeDetails::new(
+        "cxx::WeakPtr",
+        "std::weak_ptr",
+        Behavior::CxxContainerByValueSafe,
+        None,
+    ));
     db.insert(TypeDetails::new(
         "cxx::CxxString",
         "std::string",
         Behavior::CxxString,
         None,
     ));
+    db.insert(TypeDetails::new(
+        "Option",
+        "std::optional",
+        Behavior::CxxOptional,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::StringView",
+        "std::string_view",
+        Behavior::CxxStringView,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::Span",
+        "std::span",
+        Behavior::CxxSpan,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::Function",
+        "std::function",
+        Behavior::CxxFunction,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::Map",
+        "std::map",
+        Behavior::CxxMap,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::UnorderedMap",
+        "std::unordered_map",
+        Behavior::CxxMap,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::Pair",
+        "std::pair",
+        Behavior::CxxPair,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::Tuple",
+        "std::tuple",
+        Behavior::CxxTuple,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::Variant",
+        "std::variant",
+        Behavior::CxxVariant,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::U16String",
+        "std::u16string",
+        Behavior::CxxWideString,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "autocxx::WString",
+        "std::wstring",
+        Behavior::CxxWideString,
+        None,
+    ));
     db.insert(TypeDetails::new(
         "str",
         "rust::Str",
@@ -334,6 +542,26 @@ fn create_type_database() -> TypeDatabase {
         ));
     }
     db.insert(TypeDetails::new("bool", "bool", Behavior::CByValue, None));
+    db.insert(TypeDetails::new(
+        "usize",
+        "size_t",
+        Behavior::CByValue,
+        None,
+    ));
+    db.insert(TypeDetails::new(
+        "isize",
+        "ptrdiff_t",
+        Behavior::CByValue,
+        Some("ssize_t".into()),
+    ));
+    // Other names for the same sizes, which cxx also represents
+    // as usize and isize.
+    for (cpp_name, rs_name) in &[("uintptr_t", "usize"), ("intptr_t", "isize")] {
+        db.canonical_names.insert(
+            QualifiedName::new_from_user_input(cpp_name),
+            QualifiedName::new_from_user_input(rs_name),
+        );
+    }
 
     db.insert(TypeDetails::new(
         "std::pin::Pin",
@@ -385,6 +613,10 @@ fn create_type_database() -> TypeDatabase {
 /// types in those namespaces.
 const BINDGEN_BLOCKLIST_NAMESPACES: &[&str] = &["std", "rust"];
 
+/// The generic type in which bindgen stores bitfields. It's purely
+/// Rust, so we pass it through unchanged rather than telling cxx about it.
+pub(crate) const BINDGEN_BITFIELD_UNIT: &str = "__BindgenBitfieldUnit";
+
 /// Extra things to include in the bindgen blocklist.
 /// This is worked out basically using trial and error.
 /// Excluding std* and rust* is obvious, but the other items...
@@ -459,7 +691,7 @@ pub(crate) fn confirm_inner_type_is_acceptable_generic_payload(
 
 pub(crate) fn ensure_pointee_is_valid(ptr: &TypePtr) -> Result<(), ConvertError> {
     match *ptr.elem {
-        Type::Path(..) => Ok(()),
+        Type::Path(..) | Type::Array(..) => Ok(()),
         _ => Err(ConvertError::InvalidPointee),
     }
 }
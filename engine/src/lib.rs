@@ -58,7 +58,9 @@ use log::info;
 use autocxx_bindgen as bindgen;
 
 #[cfg(any(test, feature = "build"))]
-pub use builder::{build, expect_build, BuilderBuild, BuilderError, BuilderResult, BuilderSuccess};
+pub use builder::{
+    build, expect_build, BlockBuild, BuilderBuild, BuilderError, BuilderResult, BuilderSuccess,
+};
 pub use parse_file::{parse_file, ParseError, ParsedFile};
 
 pub use cxx_gen::HEADER;
@@ -98,7 +100,7 @@ pub enum Error {
     /// Some error occcurred in converting the bindgen-style
     /// bindings to safe cxx bindings.
     Conversion(conversion::ConvertError),
-    /// No 'generate' or 'generate_pod' was specified.
+    /// No 'generate', 'generate_pod' or 'generate_ns' was specified.
     /// It might be that in future we can simply let things work
     /// without any allowlist, in which case bindgen should generate
     /// bindings for everything. That just seems very unlikely to work
@@ -113,7 +115,7 @@ impl Display for Error {
             Error::Parsing(err) => write!(f, "The Rust file could not be parsede: {}", err)?,
             Error::NoAutoCxxInc => write!(f, "No C++ include directory was provided.")?,
             Error::Conversion(err) => write!(f, "autocxx could not generate the requested bindings. {}", err)?,
-            Error::NoGenerationRequested => write!(f, "No 'generate' or 'generate_pod' directives were found, so we would not generate any Rust bindings despite the inclusion of C++ headers.")?,
+            Error::NoGenerationRequested => write!(f, "No 'generate', 'generate_pod' or 'generate_ns' directives were found, so we would not generate any Rust bindings despite the inclusion of C++ headers.")?,
         }
         Ok(())
     }
@@ -140,7 +142,8 @@ const AUTOCXX_CLANG_ARGS: &[&str; 4] = &["-x", "c++", "-std=c++14", "-DBINDGEN"]
 /// to rerun the build process if any such file changes in future.
 pub trait RebuildDependencyRecorder: std::fmt::Debug {
     /// Records that this autocxx build depends on the given
-    /// header file. Full paths will be provided.
+    /// header file (or other input file, such as one specified by
+    /// `config_file!`). Full paths will be provided.
     fn record_header_file_dependency(&self, filename: &str);
 }
 
@@ -271,7 +274,7 @@ impl IncludeCppEngine {
             self.config
                 .inclusions
                 .iter()
-                .map(|path| format!("#include \"{}\"\n", path)),
+                .map(|inc| format!("{}\n", inc.to_include_directive())),
             "",
         )
     }
@@ -290,6 +293,7 @@ impl IncludeCppEngine {
             })
             .enable_cxx_namespaces()
             .generate_inline_functions(true)
+            .array_pointers_in_arguments(true)
             .layout_tests(false); // TODO revisit later
         for item in known_types::get_initial_blocklist() {
             builder = builder.blocklist_item(item);
@@ -304,6 +308,19 @@ impl IncludeCppEngine {
                 .allowlist_function(a)
                 .allowlist_var(a);
         }
+        for ns in self.config.type_config.allowlisted_namespaces() {
+            let pattern = format!("{}::.*", ns);
+            builder = builder
+                .allowlist_type(&pattern)
+                .allowlist_function(&pattern)
+                .allowlist_var(&pattern);
+        }
+        for pattern in self.config.type_config.allowlist_patterns() {
+            builder = builder
+                .allowlist_type(pattern.as_str())
+                .allowlist_function(pattern.as_str())
+                .allowlist_var(pattern.as_str());
+        }
 
         builder
     }
@@ -360,8 +377,18 @@ impl IncludeCppEngine {
             return Err(Error::NoGenerationRequested);
         }
 
+        // Arguments specified within this include_cpp! block come after
+        // those passed in for the whole file, so that they can override them.
+        let extra_clang_args: Vec<&str> = extra_clang_args
+            .iter()
+            .copied()
+            .chain(self.config.clang_args.iter().map(String::as_str))
+            .collect();
         let mut builder = self.make_bindgen_builder(&inc_dirs, &extra_clang_args);
         if let Some(dep_recorder) = dep_recorder {
+            for config_file in &self.config.config_files {
+                dep_recorder.record_header_file_dependency(&config_file.to_string_lossy());
+            }
             builder = builder.parse_callbacks(Box::new(AutocxxParseCallbacks(dep_recorder)));
         }
         let header_contents = self.build_header();
@@ -383,12 +410,13 @@ impl IncludeCppEngine {
             )
             .map_err(Error::Conversion)?;
         let mut items = conversion.rs;
+        let mod_name = self.config.get_mod_name();
         let mut new_bindings: ItemMod = parse_quote! {
             #[allow(non_snake_case)]
             #[allow(dead_code)]
             #[allow(non_upper_case_globals)]
             #[allow(non_camel_case_types)]
-            mod ffi {
+            mod #mod_name {
             }
         };
         new_bindings.content.as_mut().unwrap().1.append(&mut items);
@@ -439,6 +467,12 @@ impl IncludeCppEngine {
         Ok(GeneratedCpp(files))
     }
 
+    /// Return any additional compiler arguments specified within this
+    /// include_cpp invocation, e.g. by `cpp_std!` or `define!`.
+    pub fn clang_args(&self) -> &[String] {
+        &self.config.clang_args
+    }
+
     /// Return the include directories used for this include_cpp invocation.
     pub fn include_dirs(&self) -> &Vec<PathBuf> {
         match &self.state {
@@ -15,7 +15,7 @@
 use proc_macro2::Span;
 use std::iter::Peekable;
 use std::{fmt::Display, sync::Arc};
-use syn::{parse_quote, Ident, PathSegment, TypePath};
+use syn::{parse_quote, Expr, ExprLit, Ident, Lit, PathSegment, TypeArray, TypePath};
 
 use crate::known_types::known_types;
 
@@ -23,6 +23,16 @@ pub(crate) fn make_ident<S: AsRef<str>>(id: S) -> Ident {
     Ident::new(id.as_ref(), Span::call_site())
 }
 
+/// The length of an array type, as emitted by bindgen (e.g. `[u32; 4usize]`).
+pub(crate) fn array_len(arr: &TypeArray) -> Option<usize> {
+    match &arr.len {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(len), ..
+        }) => len.base10_parse().ok(),
+        _ => None,
+    }
+}
+
 /// Newtype wrapper for a C++ namespace.
 #[derive(Debug, PartialEq, PartialOrd, Eq, Hash, Clone)]
 #[allow(clippy::rc_buffer)]
@@ -13,21 +13,71 @@
 // limitations under the License.
 
 use autocxx_engine::{
-    build as engine_build, expect_build as engine_expect_build, BuilderBuild, BuilderError,
-    RebuildDependencyRecorder,
+    build as engine_build, expect_build as engine_expect_build, BlockBuild, BuilderBuild,
+    BuilderError, BuilderSuccess, RebuildDependencyRecorder,
 };
 use std::{collections::HashSet, io::Write, sync::Mutex};
-use std::{ffi::OsStr, path::Path};
+use std::{ffi::OsStr, io, path::Path, process};
 
 /// Build autocxx C++ files and return a cc::Build you can use to build
 /// more from a build.rs file.
 /// You need to provide the Rust file path and the iterator of paths
 /// which should be used as include directories.
+/// If any `include_cpp` block has its own compiler arguments, use
+/// [build_with_block_builds] instead.
 pub fn build<P1, I, T>(
     rs_file: P1,
     autocxx_incs: I,
     extra_clang_args: &[&str],
 ) -> Result<BuilderBuild, BuilderError>
+where
+    P1: AsRef<Path>,
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    build_with_block_builds(rs_file, autocxx_incs, extra_clang_args).and_then(
+        |(builder, block_builds)| {
+            if block_builds.is_empty() {
+                Ok(builder)
+            } else {
+                Err(BuilderError::BlockBuildsNotHandled(
+                    block_builds
+                        .iter()
+                        .map(|b| b.lib_name().to_string())
+                        .collect(),
+                ))
+            }
+        },
+    )
+}
+
+/// Builds successfully, or exits the process displaying a suitable
+/// message.
+pub fn expect_build<P1, I, T>(
+    rs_file: P1,
+    autocxx_incs: I,
+    extra_clang_args: &[&str],
+) -> BuilderBuild
+where
+    P1: AsRef<Path>,
+    I: IntoIterator<Item = T>,
+    T: AsRef<OsStr>,
+{
+    build(rs_file, autocxx_incs, extra_clang_args).unwrap_or_else(|err| {
+        let _ = writeln!(io::stderr(), "\n\nautocxx error: {}\n\n", err);
+        process::exit(1);
+    })
+}
+
+/// Like [build], but also returns a separate build for each `include_cpp`
+/// block with its own compiler arguments (from `cpp_std!`, `define!` or
+/// `clang_arg!`). Configure each of those as you do the main build, then
+/// call [BlockBuild::compile].
+pub fn build_with_block_builds<P1, I, T>(
+    rs_file: P1,
+    autocxx_incs: I,
+    extra_clang_args: &[&str],
+) -> Result<(BuilderBuild, Vec<BlockBuild>), BuilderError>
 where
     P1: AsRef<Path>,
     I: IntoIterator<Item = T>,
@@ -40,29 +90,33 @@ where
         extra_clang_args,
         Some(Box::new(CargoRebuildDependencyRecorder::new())),
     )
-    .map(|r| r.0)
+    .map(split_block_builds)
 }
 
-/// Builds successfully, or exits the process displaying a suitable
-/// message.
-pub fn expect_build<P1, I, T>(
+/// Like [build_with_block_builds], but exits the process displaying a
+/// suitable message on failure.
+pub fn expect_build_with_block_builds<P1, I, T>(
     rs_file: P1,
     autocxx_incs: I,
     extra_clang_args: &[&str],
-) -> BuilderBuild
+) -> (BuilderBuild, Vec<BlockBuild>)
 where
     P1: AsRef<Path>,
     I: IntoIterator<Item = T>,
     T: AsRef<OsStr>,
 {
     setup_logging();
-    engine_expect_build(
+    split_block_builds(engine_expect_build(
         rs_file,
         autocxx_incs,
         extra_clang_args,
         Some(Box::new(CargoRebuildDependencyRecorder::new())),
-    )
-    .0
+    ))
+}
+
+fn split_block_builds(mut success: BuilderSuccess) -> (BuilderBuild, Vec<BlockBuild>) {
+    let block_builds = success.take_block_builds();
+    (success.0, block_builds)
 }
 
 fn setup_logging() {
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
+
 use proc_macro2::Span;
 use syn::Result as ParseResult;
 use syn::{
@@ -19,7 +21,8 @@ use syn::{
     Token,
 };
 
-use crate::type_config::TypeConfig;
+use crate::config_file::ConfigFile;
+use crate::type_config::{Pattern, TypeConfig};
 
 #[derive(PartialEq, Clone, Debug, Hash)]
 pub enum UnsafePolicy {
@@ -52,13 +55,78 @@ impl Parse for UnsafePolicy {
     }
 }
 
+/// A header to be included, either as `#include "foo.h"` or as
+/// `#include <foo.h>`.
+#[derive(PartialEq, Clone, Debug, Hash)]
+pub enum CppInclusion {
+    Quoted(String),
+    System(String),
+}
+
+impl CppInclusion {
+    pub fn path(&self) -> &str {
+        match self {
+            CppInclusion::Quoted(path) | CppInclusion::System(path) => path,
+        }
+    }
+
+    /// The C++ `#include` directive for this header.
+    pub fn to_include_directive(&self) -> String {
+        match self {
+            CppInclusion::Quoted(path) => format!("#include \"{}\"", path),
+            CppInclusion::System(path) => format!("#include <{}>", path),
+        }
+    }
+}
+
+impl Parse for CppInclusion {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        if input.parse::<Option<Token![<]>>()?.is_none() {
+            let hdr: syn::LitStr = input.parse()?;
+            return Ok(CppInclusion::Quoted(hdr.value()));
+        }
+        // There's no literal here, so reassemble the path from the tokens
+        // up to the closing angle bracket, as cxx does.
+        let mut path = String::new();
+        while input.parse::<Option<Token![>]>>()?.is_none() {
+            if input.is_empty() {
+                return Err(input.error("expected > to end the header name"));
+            }
+            let token: proc_macro2::TokenTree = input.parse()?;
+            path.push_str(&token.to_string());
+        }
+        if path.is_empty() {
+            return Err(input.error("expected a header name"));
+        }
+        Ok(CppInclusion::System(path))
+    }
+}
+
 #[derive(Hash, Debug)]
 pub struct IncludeCppConfig {
-    pub inclusions: Vec<String>,
+    pub inclusions: Vec<CppInclusion>,
     pub exclude_utilities: bool,
     pub unsafe_policy: UnsafePolicy,
     pub type_config: TypeConfig,
     pub parse_only: bool,
+    /// Additional arguments to pass to clang for this block only,
+    /// from `cpp_std!`, `define!` and `clang_arg!` directives.
+    pub clang_args: Vec<String>,
+    mod_name: Option<syn::Ident>,
+    /// Any files specified by `config_file!`, whose contents have been
+    /// merged into this configuration.
+    pub config_files: Vec<PathBuf>,
+    config_file_contents: Vec<String>,
+}
+
+impl IncludeCppConfig {
+    /// The name of the mod into which the bindings are generated;
+    /// `ffi` unless specified with `name!`.
+    pub fn get_mod_name(&self) -> syn::Ident {
+        self.mod_name
+            .clone()
+            .unwrap_or_else(|| syn::Ident::new("ffi", Span::call_site()))
+    }
 }
 
 impl Parse for IncludeCppConfig {
@@ -73,6 +141,10 @@ impl Parse for IncludeCppConfig {
         let mut exclude_utilities = false;
         let mut type_config = TypeConfig::new();
         let mut unsafe_policy = UnsafePolicy::AllFunctionsUnsafe;
+        let mut clang_args = Vec::new();
+        let mut mod_name = None;
+        let mut config_files = Vec::new();
+        let mut config_file_contents = Vec::new();
 
         while !input.is_empty() {
             let has_hexathorpe = input.parse::<Option<syn::Token![#]>>()?.is_some();
@@ -81,8 +153,7 @@ impl Parse for IncludeCppConfig {
                 if ident != "include" {
                     return Err(syn::Error::new(ident.span(), "expected include"));
                 }
-                let hdr: syn::LitStr = input.parse()?;
-                inclusions.push(hdr.value());
+                inclusions.push(input.parse()?);
             } else {
                 input.parse::<Option<syn::Token![!]>>()?;
                 if ident == "generate" || ident == "generate_pod" {
@@ -93,11 +164,136 @@ impl Parse for IncludeCppConfig {
                     if ident == "generate_pod" {
                         type_config.note_pod_request(generate.value());
                     }
+                } else if ident == "generate_ns" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let ns: syn::LitStr = args.parse()?;
+                    type_config.add_namespace_to_allowlist(ns.value());
+                } else if ident == "generate_pattern" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let pattern = parse_pattern(&args)?;
+                    type_config.add_pattern_to_allowlist(pattern);
                 } else if ident == "block" {
                     let args;
                     syn::parenthesized!(args in input);
                     let generate: syn::LitStr = args.parse()?;
                     type_config.add_to_blocklist(generate.value());
+                } else if ident == "block_pattern" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let pattern = parse_pattern(&args)?;
+                    type_config.add_pattern_to_blocklist(pattern);
+                } else if ident == "rename" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let cpp_name: syn::LitStr = args.parse()?;
+                    args.parse::<syn::Token![,]>()?;
+                    let rust_name: syn::LitStr = args.parse()?;
+                    if rust_name.parse::<syn::Ident>().is_err() {
+                        return Err(syn::Error::new(
+                            rust_name.span(),
+                            "expected a valid Rust identifier",
+                        ));
+                    }
+                    type_config.add_rename(cpp_name.value(), rust_name.value());
+                } else if ident == "concrete" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let cpp_definition: syn::LitStr = args.parse()?;
+                    args.parse::<syn::Token![,]>()?;
+                    let rust_name: syn::LitStr = args.parse()?;
+                    if rust_name.parse::<syn::Ident>().is_err() {
+                        return Err(syn::Error::new(
+                            rust_name.span(),
+                            "expected a valid Rust identifier",
+                        ));
+                    }
+                    type_config.add_concrete_name(cpp_definition.value(), rust_name.value());
+                } else if ident == "extern_cpp_type" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let cpp_name: syn::LitStr = args.parse()?;
+                    args.parse::<syn::Token![,]>()?;
+                    let rust_path: syn::TypePath = args.parse()?;
+                    type_config.add_extern_cpp_type(cpp_name.value(), rust_path);
+                } else if ident == "safe" || ident == "unsafe_fn" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let item: syn::LitStr = args.parse()?;
+                    if ident == "safe" {
+                        type_config.mark_safe(item.value());
+                    } else {
+                        type_config.mark_unsafe(item.value());
+                    }
+                } else if ident == "slice" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let cpp_name: syn::LitStr = args.parse()?;
+                    args.parse::<syn::Token![,]>()?;
+                    let data: syn::LitStr = args.parse()?;
+                    args.parse::<syn::Token![,]>()?;
+                    let len: syn::LitStr = args.parse()?;
+                    type_config.add_slice_params(cpp_name.value(), data.value(), len.value());
+                } else if ident == "cpp_std" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let std: syn::LitStr = args.parse()?;
+                    clang_args.push(format!("-std={}", std.value()));
+                } else if ident == "define" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let name: syn::LitStr = args.parse()?;
+                    if args.parse::<Option<syn::Token![,]>>()?.is_some() {
+                        let value: syn::LitStr = args.parse()?;
+                        clang_args.push(format!("-D{}={}", name.value(), value.value()));
+                    } else {
+                        clang_args.push(format!("-D{}", name.value()));
+                    }
+                } else if ident == "clang_arg" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let arg: syn::LitStr = args.parse()?;
+                    clang_args.push(arg.value());
+                } else if ident == "name" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let name: syn::Ident = args.parse()?;
+                    mod_name = Some(name);
+                } else if ident == "config_file" {
+                    let args;
+                    syn::parenthesized!(args in input);
+                    let path: syn::LitStr = args.parse()?;
+                    let config_file = ConfigFile::load(&path.value())
+                        .map_err(|e| syn::Error::new(path.span(), e))?;
+                    inclusions.extend(config_file.headers.into_iter().map(CppInclusion::Quoted));
+                    inclusions.extend(
+                        config_file
+                            .system_headers
+                            .into_iter()
+                            .map(CppInclusion::System),
+                    );
+                    for generate in config_file.generate {
+                        type_config.add_to_allowlist(generate);
+                    }
+                    for generate in config_file.generate_pod {
+                        type_config.add_to_allowlist(generate.clone());
+                        type_config.note_pod_request(generate);
+                    }
+                    for block in config_file.block {
+                        type_config.add_to_blocklist(block);
+                    }
+                    if let Some(safety) = config_file.safety {
+                        unsafe_policy = syn::parse_str(&safety).map_err(|_| {
+                            syn::Error::new(
+                                path.span(),
+                                format!("invalid safety policy in config file: {}", safety),
+                            )
+                        })?;
+                    }
+                    clang_args.extend(config_file.clang_args);
+                    config_files.push(config_file.path);
+                    config_file_contents.push(config_file.contents);
                 } else if ident == "parse_only" {
                     parse_only = true;
                 } else if ident == "exclude_utilities" {
@@ -109,7 +305,7 @@ impl Parse for IncludeCppConfig {
                 } else {
                     return Err(syn::Error::new(
                         ident.span(),
-                        "expected generate, generate_pod, nested_type, safety or exclude_utilities",
+                        "expected generate, generate_pod, generate_ns, generate_pattern, block, block_pattern, rename, concrete, extern_cpp_type, nested_type, name, config_file, safety, safe, unsafe_fn, slice, cpp_std, define, clang_arg or exclude_utilities",
                     ));
                 }
             }
@@ -127,13 +323,27 @@ impl Parse for IncludeCppConfig {
             unsafe_policy,
             type_config,
             parse_only,
+            clang_args,
+            mod_name,
+            config_files,
+            config_file_contents,
         })
     }
 }
 
+fn parse_pattern(input: ParseStream) -> ParseResult<Pattern> {
+    let lit: syn::LitStr = input.parse()?;
+    Pattern::new(lit.value()).map_err(|e| {
+        syn::Error::new(
+            lit.span(),
+            format!("invalid regular expression {}: {}", lit.value(), e),
+        )
+    })
+}
+
 #[cfg(test)]
 mod parse_tests {
-    use crate::config::UnsafePolicy;
+    use crate::config::{CppInclusion, IncludeCppConfig, UnsafePolicy};
     use syn::parse_quote;
     #[test]
     fn test_safety_unsafe() {
@@ -156,4 +366,194 @@ mod parse_tests {
         let us: UnsafePolicy = parse_quote! {};
         assert_eq!(us, UnsafePolicy::AllFunctionsUnsafe)
     }
+
+    #[test]
+    fn test_generate_ns() {
+        let config: IncludeCppConfig = parse_quote! {
+            generate_ns!("base::net")
+        };
+        let tc = &config.type_config;
+        assert!(!tc.allowlist_is_empty());
+        assert!(tc.is_on_allowlist("base::net::Socket"));
+        assert!(tc.is_on_allowlist("base::net::http::Request"));
+        assert!(!tc.is_on_allowlist("base::network::Socket"));
+        assert!(!tc.is_on_allowlist("base::Widget"));
+    }
+
+    #[test]
+    fn test_generate_pattern() {
+        let config: IncludeCppConfig = parse_quote! {
+            generate_pattern!("base::.*Observer")
+            block_pattern!("base::.*Impl")
+        };
+        let tc = &config.type_config;
+        assert!(tc.is_on_allowlist("base::WidgetObserver"));
+        assert!(!tc.is_on_allowlist("base::WidgetObserverList"));
+        assert!(!tc.is_on_allowlist("other::base::WidgetObserver"));
+        assert!(tc.is_on_blocklist("base::WidgetImpl"));
+        assert!(!tc.is_on_blocklist("base::Widget"));
+    }
+
+    #[test]
+    fn test_generate_pattern_invalid() {
+        let r: syn::Result<IncludeCppConfig> = syn::parse2(quote::quote! {
+            generate_pattern!("base::(")
+        });
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_rename() {
+        let config: IncludeCppConfig = parse_quote! {
+            rename!("ns::CFooBarImpl", "FooBar")
+            rename!("ns::Foo::get_thing_2", "thing")
+        };
+        let tc = &config.type_config;
+        assert_eq!(tc.get_rust_name("ns::CFooBarImpl"), Some("FooBar"));
+        assert_eq!(tc.get_rust_name("ns::Foo::get_thing_2"), Some("thing"));
+        assert_eq!(tc.get_rust_name("ns::Foo"), None);
+    }
+
+    #[test]
+    fn test_rename_invalid_ident() {
+        let r: syn::Result<IncludeCppConfig> = syn::parse2(quote::quote! {
+            rename!("ns::Foo", "not valid")
+        });
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_extern_cpp_type() {
+        let config: IncludeCppConfig = parse_quote! {
+            extern_cpp_type!("base::Widget", other_crate::ffi::base::Widget)
+        };
+        let expected: syn::TypePath = parse_quote! { other_crate::ffi::base::Widget };
+        assert_eq!(
+            config.type_config.get_extern_cpp_type("base::Widget"),
+            Some(&expected)
+        );
+        assert!(config
+            .type_config
+            .get_extern_cpp_type("base::Gadget")
+            .is_none());
+    }
+
+    #[test]
+    fn test_concrete() {
+        let config: IncludeCppConfig = parse_quote! {
+            concrete!("std::map<int, Foo>", "FooMap")
+        };
+        let tc = &config.type_config;
+        assert_eq!(tc.get_concrete_name("std::map<int, Foo>"), Some("FooMap"));
+        assert_eq!(tc.get_concrete_name("std::map<int,Foo>"), Some("FooMap"));
+        assert_eq!(tc.get_concrete_name("std::map<int, Bar>"), None);
+    }
+
+    #[test]
+    fn test_safety_overrides() {
+        let config: IncludeCppConfig = parse_quote! {
+            safe!("base::Widget::size")
+            unsafe_fn!("base::Widget::poke_raw")
+        };
+        let tc = &config.type_config;
+        assert_eq!(tc.get_unsafe_override("base::Widget::size"), Some(false));
+        assert_eq!(tc.get_unsafe_override("base::Widget::poke_raw"), Some(true));
+        assert_eq!(tc.get_unsafe_override("base::Widget::other"), None);
+    }
+
+    #[test]
+    fn test_slice_params() {
+        let config: IncludeCppConfig = parse_quote! {
+            slice!("net::send", "data", "len")
+            slice!("net::copy", "src", "src_len")
+            slice!("net::copy", "dest", "dest_len")
+        };
+        let tc = &config.type_config;
+        assert_eq!(
+            tc.get_slice_params("net::send").collect::<Vec<_>>(),
+            vec![("data", "len")]
+        );
+        assert_eq!(
+            tc.get_slice_params("net::copy").collect::<Vec<_>>(),
+            vec![("src", "src_len"), ("dest", "dest_len")]
+        );
+        assert_eq!(tc.get_slice_params("net::recv").count(), 0);
+    }
+
+    #[test]
+    fn test_clang_args() {
+        let config: IncludeCppConfig = parse_quote! {
+            cpp_std!("c++17")
+            define!("USE_FOO")
+            define!("FOO_LEVEL", "2")
+            clang_arg!("-fno-exceptions")
+        };
+        assert_eq!(
+            config.clang_args,
+            vec![
+                "-std=c++17",
+                "-DUSE_FOO",
+                "-DFOO_LEVEL=2",
+                "-fno-exceptions"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_system_include() {
+        let inc: CppInclusion = parse_quote! { <vendor/lib.h> };
+        assert_eq!(inc, CppInclusion::System("vendor/lib.h".to_string()));
+        assert_eq!(inc.to_include_directive(), "#include <vendor/lib.h>");
+        let inc: CppInclusion = parse_quote! { "foo.h" };
+        assert_eq!(inc, CppInclusion::Quoted("foo.h".to_string()));
+        assert_eq!(inc.to_include_directive(), "#include \"foo.h\"");
+    }
+
+    #[test]
+    fn test_mod_name() {
+        let config: IncludeCppConfig = parse_quote! {};
+        assert_eq!(config.get_mod_name(), "ffi");
+        let config: IncludeCppConfig = parse_quote! {
+            name!(net_ffi)
+        };
+        assert_eq!(config.get_mod_name(), "net_ffi");
+    }
+
+    #[test]
+    fn test_config_file() {
+        let tdir = tempfile::tempdir().unwrap();
+        let path = tdir.path().join("autocxx.toml");
+        std::fs::write(
+            &path,
+            r#"
+                headers = ["net/socket.h"]
+                generate = ["net::Socket"]
+                generate_pod = ["net::Address"]
+                block = ["net::Internal"]
+                safety = "unsafe_ffi"
+                clang_args = ["-DUSE_FOO"]
+            "#,
+        )
+        .unwrap();
+        let config: IncludeCppConfig =
+            syn::parse_str(&format!("config_file!({:?})", path.to_str().unwrap())).unwrap();
+        assert_eq!(
+            config.inclusions,
+            vec![CppInclusion::Quoted("net/socket.h".to_string())]
+        );
+        let tc = &config.type_config;
+        assert!(tc.is_on_allowlist("net::Socket"));
+        assert!(tc.is_on_allowlist("net::Address"));
+        assert!(tc.is_on_blocklist("net::Internal"));
+        assert_eq!(config.unsafe_policy, UnsafePolicy::AllFunctionsSafe);
+        assert_eq!(config.clang_args, vec!["-DUSE_FOO"]);
+        assert_eq!(config.config_files, vec![path]);
+    }
+
+    #[test]
+    fn test_config_file_missing() {
+        let result: syn::Result<IncludeCppConfig> =
+            syn::parse_str(r#"config_file!("/no/such/autocxx_config.toml")"#);
+        assert!(result.is_err());
+    }
 }
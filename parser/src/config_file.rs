@@ -0,0 +1,69 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// Configuration loaded from a TOML file named by a `config_file!`
+/// directive, so that long lists of directives can be shared between
+/// several `include_cpp!` blocks and the `autocxx-gen` tool. For example:
+///
+/// ```toml
+/// headers = ["net/socket.h"]
+/// system_headers = ["vendor/lib.h"]
+/// generate = ["net::Socket", "net::connect"]
+/// generate_pod = ["net::Address"]
+/// block = ["net::Internal"]
+/// safety = "unsafe_ffi"
+/// clang_args = ["-DUSE_FOO"]
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ConfigFile {
+    pub(crate) headers: Vec<String>,
+    pub(crate) system_headers: Vec<String>,
+    pub(crate) generate: Vec<String>,
+    pub(crate) generate_pod: Vec<String>,
+    pub(crate) block: Vec<String>,
+    pub(crate) safety: Option<String>,
+    pub(crate) clang_args: Vec<String>,
+    /// Where this file was found.
+    #[serde(skip)]
+    pub(crate) path: PathBuf,
+    /// The raw contents of the file, so that they can contribute to
+    /// the hash of the `include_cpp!` configuration.
+    #[serde(skip)]
+    pub(crate) contents: String,
+}
+
+impl ConfigFile {
+    /// Load a config file. Relative paths are interpreted relative to
+    /// the directory containing the crate's `Cargo.toml`, if known.
+    pub(crate) fn load(path: &str) -> Result<Self, String> {
+        let path = match std::env::var_os("CARGO_MANIFEST_DIR") {
+            Some(manifest_dir) => PathBuf::from(manifest_dir).join(path),
+            None => PathBuf::from(path),
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("unable to read config file {}: {}", path.display(), e))?;
+        let config: ConfigFile = toml::from_str(&contents)
+            .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?;
+        Ok(ConfigFile {
+            path,
+            contents,
+            ..config
+        })
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 mod config;
+mod config_file;
 pub mod file_locations;
 mod type_config;
 
@@ -21,7 +22,7 @@ use std::{
     hash::{Hash, Hasher},
 };
 
-pub use config::{IncludeCppConfig, UnsafePolicy};
+pub use config::{CppInclusion, IncludeCppConfig, UnsafePolicy};
 use file_locations::FileLocationStrategy;
 use proc_macro2::TokenStream as TokenStream2;
 use syn::Result as ParseResult;
@@ -30,7 +31,7 @@ use syn::{
     Macro,
 };
 
-pub use type_config::TypeConfig;
+pub use type_config::{Pattern, TypeConfig};
 
 /// Core of the autocxx engine. See `generate` for most details
 /// on how this works.
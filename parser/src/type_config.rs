@@ -12,6 +12,42 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::hash::{Hash, Hasher};
+
+use regex::Regex;
+use syn::TypePath;
+
+/// A regular expression used to match the C++ names of APIs,
+/// for instance as given to `generate_pattern!`. As with bindgen,
+/// the expression must match the entire name.
+#[derive(Debug)]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub(crate) fn new(source: String) -> Result<Self, regex::Error> {
+        let regex = Regex::new(&format!("^(?:{})$", source))?;
+        Ok(Self { source, regex })
+    }
+
+    pub fn is_match(&self, cpp_name: &str) -> bool {
+        self.regex.is_match(cpp_name)
+    }
+
+    /// The pattern as originally specified by the user.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl Hash for Pattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state)
+    }
+}
+
 /// Configuration about types.
 /// At present this is very minimal; in future we should roll
 /// known_types.rs into this and possibly other things as well.
@@ -20,6 +56,15 @@ pub struct TypeConfig {
     pod_requests: Vec<String>,
     allowlist: Vec<String>, // not TypeName as it may be funcs not types.
     blocklist: Vec<String>, // not TypeName as it may be funcs not types.
+    allowlisted_namespaces: Vec<String>,
+    allowlist_patterns: Vec<Pattern>,
+    blocklist_patterns: Vec<Pattern>,
+    renames: Vec<(String, String)>,
+    extern_cpp_types: Vec<(String, TypePath)>,
+    concrete_names: Vec<(String, String)>,
+    safe_items: Vec<String>,
+    unsafe_items: Vec<String>,
+    slice_params: Vec<(String, String, String)>,
 }
 
 impl TypeConfig {
@@ -35,10 +80,46 @@ impl TypeConfig {
         self.allowlist.push(item);
     }
 
+    pub(crate) fn add_namespace_to_allowlist(&mut self, ns: String) {
+        self.allowlisted_namespaces.push(ns);
+    }
+
+    pub(crate) fn add_pattern_to_allowlist(&mut self, pattern: Pattern) {
+        self.allowlist_patterns.push(pattern);
+    }
+
     pub(crate) fn add_to_blocklist(&mut self, item: String) {
         self.blocklist.push(item);
     }
 
+    pub(crate) fn add_pattern_to_blocklist(&mut self, pattern: Pattern) {
+        self.blocklist_patterns.push(pattern);
+    }
+
+    pub(crate) fn add_rename(&mut self, cpp_name: String, rust_name: String) {
+        self.renames.push((cpp_name, rust_name));
+    }
+
+    pub(crate) fn add_extern_cpp_type(&mut self, cpp_name: String, rust_path: TypePath) {
+        self.extern_cpp_types.push((cpp_name, rust_path));
+    }
+
+    pub(crate) fn add_concrete_name(&mut self, cpp_definition: String, rust_name: String) {
+        self.concrete_names.push((cpp_definition, rust_name));
+    }
+
+    pub(crate) fn mark_safe(&mut self, cpp_name: String) {
+        self.safe_items.push(cpp_name);
+    }
+
+    pub(crate) fn mark_unsafe(&mut self, cpp_name: String) {
+        self.unsafe_items.push(cpp_name);
+    }
+
+    pub(crate) fn add_slice_params(&mut self, cpp_name: String, data: String, len: String) {
+        self.slice_params.push((cpp_name, data, len));
+    }
+
     pub fn get_pod_requests(&self) -> &[String] {
         &self.pod_requests
     }
@@ -47,8 +128,18 @@ impl TypeConfig {
         self.allowlist.iter()
     }
 
+    pub fn allowlisted_namespaces(&self) -> impl Iterator<Item = &String> {
+        self.allowlisted_namespaces.iter()
+    }
+
+    pub fn allowlist_patterns(&self) -> impl Iterator<Item = &Pattern> {
+        self.allowlist_patterns.iter()
+    }
+
     pub fn allowlist_is_empty(&self) -> bool {
         self.allowlist.is_empty()
+            && self.allowlisted_namespaces.is_empty()
+            && self.allowlist_patterns.is_empty()
     }
 
     /// Whether this type is on the allowlist specified by the user.
@@ -59,15 +150,91 @@ impl TypeConfig {
     ///    we pass to cxx.
     /// This second pass may seem redundant. But sometimes bindgen generates
     /// unnecessary stuff.
+    ///
+    /// Anything declared within a namespace passed to `generate_ns!`
+    /// (including nested namespaces), or matching a `generate_pattern!`,
+    /// is considered to be on the allowlist.
     pub fn is_on_allowlist(&self, cpp_name: &str) -> bool {
         self.allowlist.contains(&cpp_name.to_string())
+            || self
+                .allowlisted_namespaces
+                .iter()
+                .any(|ns| cpp_name.starts_with(&format!("{}::", ns)))
+            || self.allowlist_patterns.iter().any(|p| p.is_match(cpp_name))
     }
 
     pub fn is_on_blocklist(&self, cpp_name: &str) -> bool {
         self.blocklist.contains(&cpp_name.to_string())
+            || self.blocklist_patterns.iter().any(|p| p.is_match(cpp_name))
+    }
+
+    /// The Rust name requested by the user for a given C++ type,
+    /// function or method, if any.
+    pub fn get_rust_name(&self, cpp_name: &str) -> Option<&str> {
+        self.renames
+            .iter()
+            .find(|(k, _)| k == cpp_name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The Rust path to an existing binding for this C++ type, if the
+    /// user has told us (via `extern_cpp_type!`) that one exists
+    /// elsewhere.
+    pub fn get_extern_cpp_type(&self, cpp_name: &str) -> Option<&TypePath> {
+        self.extern_cpp_types
+            .iter()
+            .find(|(k, _)| k == cpp_name)
+            .map(|(_, v)| v)
+    }
+
+    /// The Rust name requested by the user (via `concrete!`) for a given
+    /// instantiation of a C++ template, if any. Whitespace is ignored when
+    /// comparing the C++ spelling.
+    pub fn get_concrete_name(&self, cpp_definition: &str) -> Option<&str> {
+        let cpp_definition = strip_whitespace(cpp_definition);
+        self.concrete_names
+            .iter()
+            .find(|(k, _)| strip_whitespace(k) == cpp_definition)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the user has chosen this Rust name (via `concrete!`) for
+    /// any instantiation of a C++ template.
+    pub fn is_chosen_concrete_name(&self, rust_name: &str) -> bool {
+        self.concrete_names.iter().any(|(_, v)| v == rust_name)
+    }
+
+    /// Whether the user has specified (via `safe!` or `unsafe_fn!`) that
+    /// this function or method should be `unsafe`, overriding the
+    /// general safety policy. `None` if there's no such directive.
+    pub fn get_unsafe_override(&self, cpp_name: &str) -> Option<bool> {
+        if self.unsafe_items.iter().any(|item| item == cpp_name) {
+            Some(true)
+        } else if self.safe_items.iter().any(|item| item == cpp_name) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Pairs of (data, length) parameters which the user has asked (via
+    /// `slice!`) to be combined into a single slice parameter for
+    /// this function or method.
+    pub fn get_slice_params<'a>(
+        &'a self,
+        cpp_name: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.slice_params
+            .iter()
+            .filter(move |(k, _, _)| k == cpp_name)
+            .map(|(_, data, len)| (data.as_str(), len.as_str()))
     }
 
     pub fn get_blocklist(&self) -> impl Iterator<Item = &String> {
         self.blocklist.iter()
     }
 }
+
+fn strip_whitespace(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace()).collect()
+}
@@ -96,7 +96,9 @@ use autocxx_engine::IncludeCppEngine;
 /// Within the brackets of the `include_cxx!(...)` macro, you should provide
 /// a list of at least the following:
 ///
-/// * `#include "cpp_header.h"`: a header filename to parse and include
+/// * `#include "cpp_header.h"`: a header filename to parse and include.
+///   `#include <cpp_header.h>` is also accepted for headers which should
+///   be found on the system include path.
 /// * `generate!("type_or_function_name")`: a type or function name whose declaration
 ///   should be made available to C++.
 /// * Possibly, `safety!(unsafe)` - see discussion of `unsafe` later.
@@ -157,6 +159,12 @@ use autocxx_engine::IncludeCppEngine;
 ///
 /// None of that happens if you use `exclude_utilities`.
 ///
+/// Functions that accept a `std::string_view` accept a `&str`. Functions
+/// which return a `std::string_view` return a
+/// `Result<&str, std::str::Utf8Error>`, which is an error if the string
+/// isn't UTF-8. The `&str`'s lifetime is tied to that of the receiver (or of
+/// the only reference parameter).
+///
 /// # Support for particular C++ features
 ///
 /// ## Making other C++ types
@@ -165,6 +173,44 @@ use autocxx_engine::IncludeCppEngine;
 /// gain this if they have an explicit C++ constructor; this is a limitation
 /// which should be resolved in future.
 /// This will (of course) return a `UniquePtr` containing that type.
+/// They also gain a `make_shared` associated function, which returns a
+/// `SharedPtr` instead. Overloaded constructors are numbered in the same
+/// way for both, e.g. `make_unique1` and `make_shared1`.
+///
+/// ## Callbacks
+///
+/// Functions which take a `std::function` accept a Rust closure instead.
+/// So do functions which take a C function pointer, so long as the function
+/// pointer is immediately followed by a `void*` or `const void*` parameter
+/// which is passed to the callback as its first or last argument - the
+/// usual pattern for C callbacks with user data. You don't pass that
+/// `void*` yourself.
+///
+/// ```cpp
+/// typedef void (*listener_t)(void* user_data, uint32_t event);
+/// void add_listener(listener_t listener, void* user_data);
+/// ```
+///
+/// ```ignore
+/// let handle = unsafe { ffi::add_listener(|event| println!("Event {}", event)) };
+/// ```
+///
+/// C++ may call a callback from any thread, so closures must be
+/// `Fn + Send + Sync`. A closure passed as a `std::function` is dropped
+/// when the last copy of that `std::function` is destroyed.
+///
+/// A closure passed as a function pointer is owned by a [CallbackHandle],
+/// and is dropped along with it. The function returns the handle as the
+/// second element of a tuple, after its own result, or on its own if it
+/// returns nothing. autocxx can't know how long C++ keeps a function pointer,
+/// so such functions are `unsafe`: you must keep the handle for as long as
+/// C++ might call the callback, for example until a listener has been
+/// unregistered. If that's forever, [std::mem::forget] the handle.
+///
+/// A function pointer without user data has nowhere to keep a closure, so
+/// it must be given a Rust `extern "C" fn` instead.
+///
+/// At present, callbacks may only take and return POD types by value.
 ///
 /// ## Preprocessor symbols
 ///
@@ -179,6 +225,15 @@ use autocxx_engine::IncludeCppEngine;
 /// eventually use `std::os::raw::c_int` oor `std::os::raw::c_ulong` etc.
 /// For now, this doesn't quite work: instead you need to wrap these values
 /// in a newtype wrapper such as [c_int] or [c_ulong] in this crate.
+/// `size_t` and `uintptr_t` are simply `usize`, and `ssize_t`, `ptrdiff_t`
+/// and `intptr_t` are `isize`.
+///
+/// cxx can't hold integers in a `UniquePtr`, so a `std::unique_ptr<uint32_t>`
+/// instead holds a generated newtype, `ffi::AutocxxPrimitiveUint32T(u32)`.
+/// This derefs to a `u32`, compares equal with one, and can be created
+/// from one using `into()`, so you should rarely need to name it.
+/// Non-const references to integers, as used for out-parameters, are
+/// simply `&mut u32` and so on.
 ///
 /// ## String constants
 ///
@@ -195,6 +250,24 @@ use autocxx_engine::IncludeCppEngine;
 /// assert_eq!(std::str::from_utf8(&ffi::BOB).unwrap().trim_end_matches(char::from(0)), "Hello");
 /// ```
 ///
+/// ## Wide strings
+///
+/// `std::u16string` and `std::wstring` are opaque concrete types, named
+/// like any other (so `ffi::AutocxxConcreteStdU16string` unless you use
+/// [concrete] to choose a name). Create them from Rust using `new_from_str`
+/// or `new_from_units`, and get the contents back with `to_string_lossy`
+/// or `to_units`. The units are `u16` for `std::u16string` and `u32` for
+/// `std::wstring`. Like `make_string`, these functions are not generated
+/// if you use `exclude_utilities`.
+///
+/// The character types themselves are plain integers in Rust, as bindgen
+/// gives them to us: `char16_t` is `u16`, `char32_t` is `u32`, and
+/// `wchar_t` is whichever of those matches its size on the target, so
+/// `u16` on Windows and `u32` elsewhere. They work as fields of POD
+/// structs. Functions which take or return them by value aren't yet
+/// supported: bindgen gives us no way to tell them apart from `uint16_t`
+/// and `uint32_t`, and cxx needs the exact C++ signature.
+///
 /// ## Namespaces
 ///
 /// The C++ namespace structure is reflected in mods within the generated
@@ -231,8 +304,39 @@ use autocxx_engine::IncludeCppEngine;
 /// and never have methods attached. That's therefore enough to pass them
 /// between return types and parameters of other functions within `UniquePtr`s
 /// but not really enough to do anything else with these types just yet. Hopefully,
-/// this will be improved in future. At present such types have a name
-/// `AutocxxConcrete{n}` but this may change in future.
+/// this will be improved in future. By default such types are named after the
+/// C++ spelling of the instantiation, e.g. `std::map<int, Foo>` becomes
+/// `AutocxxConcreteStdMapIntFoo`. In the rare case that two instantiations
+/// would get the same name, the second also gets a suffix derived from its
+/// full C++ spelling. Use [concrete] to choose a name instead.
+///
+/// ## Maps
+///
+/// `std::map` and `std::unordered_map` are an exception: their concrete
+/// types gain `make_unique`, `len`, `is_empty`, `contains_key`, `get`,
+/// `insert` and `iter` methods. Where the keys are integers or strings,
+/// and the values are integers, strings or POD structs, `std::map` can
+/// also be converted to and from a `BTreeMap` (`to_btree_map` and
+/// `from_btree_map`) and `std::unordered_map` to and from a `HashMap`
+/// (`to_hash_map` and `from_hash_map`). POD structs can't be keys of
+/// Rust maps, because they don't implement `Ord` or `Hash`. Keys and
+/// values are copied on insertion.
+///
+/// ## Pairs
+///
+/// Functions which return a `std::pair` by value return a Rust tuple, if
+/// both elements are POD, primitive or a string. Strings arrive as
+/// `UniquePtr<CxxString>`. Otherwise, they return a `UniquePtr` to a
+/// concrete type, with `first` and `second` getters.
+///
+/// `std::tuple` isn't supported: bindgen can't tell us the type arguments
+/// of variadic templates, so functions using it are skipped, with an
+/// explanatory error.
+///
+/// ## Variants
+///
+/// `std::variant` isn't supported either, because it's also a variadic
+/// template. Functions using it are skipped, with an explanatory error.
 ///
 /// # Internals
 ///
@@ -251,6 +355,38 @@ macro_rules! include_cpp {
             $($mac!($($arg)*))*
         }
     };
+    // System includes such as `#include <foo.h>` can't be matched by the
+    // rule above, so walk past each include in turn, keeping the whole
+    // input in square brackets to hand on afterwards.
+    (@docs [$($all:tt)*] #$include:ident $lit:literal $($rest:tt)*) => {
+        $crate::$include!{__docs}
+        $crate::include_cpp!{@docs [$($all)*] $($rest)*}
+    };
+    (@docs [$($all:tt)*] #$include:ident < $($rest:tt)*) => {
+        $crate::$include!{__docs}
+        $crate::include_cpp!{@system_path [$($all)*] $($rest)*}
+    };
+    (@docs [$($all:tt)*] $($mac:ident!($($arg:tt)*))*) => {
+        $($crate::$mac!{__docs})*
+        $crate::include_cpp_impl! {
+            $($all)*
+        }
+    };
+    (@system_path [$($all:tt)*] > $($rest:tt)*) => {
+        $crate::include_cpp!{@docs [$($all)*] $($rest)*}
+    };
+    (@system_path [$($all:tt)*] $path_part:tt $($rest:tt)*) => {
+        $crate::include_cpp!{@system_path [$($all)*] $($rest)*}
+    };
+    // Anything else is left for include_cpp_impl to report.
+    (@$state:ident [$($all:tt)*] $($rest:tt)*) => {
+        $crate::include_cpp_impl! {
+            $($all)*
+        }
+    };
+    ($($tt:tt)*) => {
+        $crate::include_cpp!{@docs [$($tt)*] $($tt)*}
+    };
 }
 
 /// Include a C++ header. A directive to be included inside
@@ -283,6 +419,131 @@ macro_rules! generate_pod {
     ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
 }
 
+/// Generate Rust bindings for everything declared within the given
+/// C++ namespace, including any nested namespaces. This is equivalent
+/// to listing each type and function in that namespace using [generate].
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! generate_ns {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Generate Rust bindings for all C++ types and functions whose
+/// fully-qualified names match the given regular expression, for
+/// example `generate_pattern!("base::.*Observer")`. The expression
+/// must match the whole name. It's an error if nothing matches.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! generate_pattern {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Choose a different Rust name for a C++ type, function or method.
+/// For example, `rename!("base::CFooBarImpl", "FooBar")` or
+/// `rename!("base::Foo::get_thing_2", "thing")`. The C++ item is
+/// still called by its original name; only the Rust name changes.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! rename {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Declare that bindings for a C++ type already exist elsewhere,
+/// for example generated by an `include_cpp!` block in another crate.
+/// For example, `extern_cpp_type!("base::Widget", other_crate::ffi::base::Widget)`.
+/// The Rust type must already implement [cxx::ExternType][autocxx_engine::cxx::ExternType].
+/// Rather than generating a new Rust type, functions in this
+/// `include_cpp!` block which use the C++ type will refer to the given Rust
+/// type, so that values can be passed between the two sets of bindings.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! extern_cpp_type {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Choose the Rust name for a particular instantiation of a C++
+/// template, for example `concrete!("std::map<int, Foo>", "FooMap")`.
+/// The C++ type should be spelled with fully-qualified names. Without
+/// this directive, a name is derived from the C++ spelling. It's an
+/// error for the name you choose to clash with any other.
+/// See the section on generic types in [include_cpp].
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! concrete {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Choose the C++ standard used when parsing the headers in this
+/// block, for example `cpp_std!("c++17")`. The default is `c++14`.
+/// Any C++ generated for this block is compiled with the same standard,
+/// in a separate build returned by `autocxx_build::build_with_block_builds`.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! cpp_std {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Define a preprocessor symbol when parsing the headers in this
+/// block, for example `define!("USE_FOO")` or `define!("FOO_LEVEL", "2")`.
+/// Any C++ generated for this block is compiled with the same definitions.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! define {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Pass an arbitrary extra argument to clang when parsing the
+/// headers in this block, for example `clang_arg!("-fno-exceptions")`.
+/// Unlike the arguments passed to the build script, this applies
+/// only to this [include_cpp] block.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! clang_arg {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Choose the name of the mod into which bindings are generated,
+/// for example `name!(net_ffi)`. By default this is `ffi`. This allows
+/// several [include_cpp] blocks to live in the same Rust module.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! name {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Load further directives from a TOML file, for example
+/// `config_file!("autocxx/net.toml")`. Relative paths are relative to
+/// the directory containing your `Cargo.toml`. The file may contain
+/// these keys, each of which behaves like the equivalent directive:
+///
+/// ```toml
+/// headers = ["net/socket.h"]
+/// system_headers = ["vendor/lib.h"]
+/// generate = ["net::Socket", "net::connect"]
+/// generate_pod = ["net::Address"]
+/// block = ["net::Internal"]
+/// safety = "unsafe_ffi"
+/// clang_args = ["-DUSE_FOO"]
+/// ```
+///
+/// When using `autocxx_build`, Cargo is told to rebuild if this file
+/// changes.
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! config_file {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
 /// Skip the normal generation of a `make_string` function
 /// and other utilities which we might generate normally.
 /// A directive to be included inside
@@ -306,6 +567,17 @@ macro_rules! block {
     ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
 }
 
+/// Block all C++ types and functions whose fully-qualified names
+/// match the given regular expression. See [block] and
+/// [generate_pattern] for details.
+///
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! block_pattern {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
 /// Specifies a global safety policy for functions generated
 /// from these headers. By default (without such a `safety!`
 /// directive) all such functions are marked as `unsafe` and
@@ -337,11 +609,58 @@ macro_rules! block {
 /// that you've analyzed all possible ways that the code
 /// can be used and you are guaranteeing to the compiler that
 /// no badness can occur. Good luck.
+///
+/// Individual functions can be exempted from this policy
+/// using [safe] and [unsafe_fn].
 #[macro_export]
 macro_rules! safety {
     ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
 }
 
+/// Declare that a particular function or method is safe to call,
+/// whatever the overall [safety] policy, for example
+/// `safe!("base::Widget::size")`. Methods are named by their class
+/// followed by the method name. Functions which take raw pointers
+/// remain `unsafe` regardless.
+///
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! safe {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Declare that a particular function or method should be marked
+/// `unsafe`, even if the overall [safety] policy says otherwise,
+/// for example `unsafe_fn!("base::Widget::poke_raw")`.
+///
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! unsafe_fn {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
+/// Combine a pointer parameter and a length parameter of a function or
+/// method into a single slice parameter, for example
+/// `slice!("net::send", "data", "len")` turns
+/// `void send(const uint8_t* data, size_t len)` into
+/// `fn send(data: &[u8])`. The length parameter must immediately follow
+/// the pointer parameter. A pointer to non-const data results in a
+/// `&mut` slice.
+///
+/// Parameters of type `std::span<const T>` are converted to `&[T]`, and
+/// `std::span<T>` to `&mut [T]`, automatically, without any need for this
+/// directive. Which are const is found from the function's mangled name,
+/// so with MSVC all spans become `&mut [T]`.
+///
+/// A directive to be included inside
+/// [include_cpp] - see [include_cpp] for general information.
+#[macro_export]
+macro_rules! slice {
+    ($($tt:tt)*) => { $crate::usage!{$($tt)*} };
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! usage {
@@ -362,7 +681,7 @@ pub use autocxx_macro::include_cpp_impl;
 macro_rules! ctype_wrapper {
     ($r:ident, $c:expr, $d:expr) => {
         #[doc=$d]
-        #[derive(Debug, Eq, Clone, PartialEq, Hash)]
+        #[derive(Debug, Eq, Clone, PartialEq, Hash, PartialOrd, Ord)]
         #[allow(non_camel_case_types)]
         #[repr(transparent)]
         pub struct $r(pub ::std::os::raw::$r);
@@ -402,6 +721,49 @@ unsafe impl autocxx_engine::cxx::ExternType for c_void {
     type Kind = autocxx_engine::cxx::kind::Trivial;
 }
 
+/// Owns the closures passed to C++ as function pointer callbacks, which
+/// are freed when this is dropped. Functions taking such callbacks return
+/// one of these, and are `unsafe` because the caller must keep it for as
+/// long as C++ might call them.
+#[must_use = "dropping this frees the callbacks, which C++ may still call"]
+#[derive(Default)]
+pub struct CallbackHandle(Vec<RegisteredCallback>);
+
+/// A closure owned by a [CallbackHandle], and how to free it.
+struct RegisteredCallback {
+    ptr: *mut (),
+    free: unsafe fn(*mut ()),
+}
+
+// Only closures which are themselves Send and Sync are registered.
+unsafe impl Send for CallbackHandle {}
+unsafe impl Sync for CallbackHandle {}
+
+impl CallbackHandle {
+    /// Takes ownership of a closure, returning a pointer to it which
+    /// remains valid until this handle is dropped.
+    #[doc(hidden)]
+    pub fn register<T: Send + Sync + 'static>(&mut self, callback: Box<T>) -> *mut T {
+        unsafe fn free<T>(ptr: *mut ()) {
+            drop(Box::from_raw(ptr as *mut T));
+        }
+        let ptr = Box::into_raw(callback);
+        self.0.push(RegisteredCallback {
+            ptr: ptr as *mut (),
+            free: free::<T>,
+        });
+        ptr
+    }
+}
+
+impl Drop for CallbackHandle {
+    fn drop(&mut self) {
+        for callback in self.0.drain(..) {
+            unsafe { (callback.free)(callback.ptr) }
+        }
+    }
+}
+
 /// autocxx couldn't generate these bindings.
 /// If you come across a method, type or function which refers to this type,
 /// it indicates that autocxx couldn't generate that binding. A documentation